@@ -1,28 +1,41 @@
-mod debug_map;
+mod asm_editor;
+mod disassembly_view;
 pub mod emulator_view;
+mod memory_view;
+mod playlist;
+mod recent_roms;
+mod screenshot;
+mod settings;
 mod ui;
 
 use std::error::Error;
 use std::fmt::Display;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Duration;
 
 use crate::app::emulator_view::EmulatorViewMode;
-use crate::chip8::screen::{self};
+use crate::chip8::screen::{self, DrawOptions, Resolution};
 use crate::chip8::{Chip8, EmulatorConfig, EmulatorEvents};
-use crate::display_bus::{AppEvents, ClientMessage};
-use crate::io::InputState;
+use crate::display_bus::{send_event, AppEvents, ClientMessage, ConnectionStatus};
+use crate::io::gamepad::{self, Gamepads};
+use crate::io::{keymap, InputState};
+use crate::paths;
+use crate::Args;
 use pixels::Error as PixError;
 use serde::{Deserialize, Serialize};
-use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
-use self::emulator_view::{receive_event_over_tcp, send_over_tcp, EmulatorView, PORT};
+use self::emulator_view::{
+    receive_event_over_tcp, reconnect, send_over_tcp, EmulatorView, ReceiveError,
+};
+use self::recent_roms::RecentRoms;
 use self::ui::Framework;
 
 pub struct App {
@@ -34,11 +47,27 @@ pub struct App {
     input_state: InputStateRef,
 }
 pub type InputStateRef = Arc<RwLock<InputState>>;
+/// Dumps the emulator memory to a file.
+pub const DUMP_MEMORY_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+/// Saves a screenshot of the display.
+pub const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+/// Switches to the next rom of the playlist.
+pub const NEXT_ROM_KEY: VirtualKeyCode = VirtualKeyCode::PageDown;
+/// Switches to the previous rom of the playlist.
+pub const PREVIOUS_ROM_KEY: VirtualKeyCode = VirtualKeyCode::PageUp;
+/// Pauses and resumes the emulator, unless it's mapped to a chip8 key.
+pub const PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::Space;
+/// Runs the emulator at [`TURBO_SPEED`] while held, unless it's mapped to a chip8 key.
+pub const TURBO_KEY: VirtualKeyCode = VirtualKeyCode::Tab;
+/// Speed multiplier while the turbo key is held.
+const TURBO_SPEED: f32 = 4.;
+/// Change of the instructions per second per scrolled line.
+const CPU_HZ_SCROLL_STEP: i32 = 60;
 impl App {
     pub fn _display_bus(&self) -> EventLoopProxy<AppEvents> {
         self.event_loop.create_proxy()
     }
-    pub fn init() -> Result<App, PixError> {
+    pub fn init(args: &Args) -> Result<App, PixError> {
         let input = WinitInputHelper::new();
         let event_loop = EventLoopBuilder::<AppEvents>::default().build();
 
@@ -52,7 +81,7 @@ impl App {
                 .unwrap()
         };
         let emulator_view = EmulatorView::new(&window)?;
-        let framework = {
+        let mut framework = {
             let window_size = window.inner_size();
             let scale_factor = window.scale_factor() as f32;
             Framework::new(
@@ -63,6 +92,15 @@ impl App {
                 &emulator_view,
             )
         };
+        framework.gui.key_map = keymap::load_or_create();
+        framework.gui.button_map = gamepad::load_or_create();
+        framework.gui.recent_roms = RecentRoms::load();
+        framework
+            .gui
+            .apply_args(args.rom.clone(), args.generation, args.cpu_hz);
+        if args.rom.is_some() {
+            framework.gui.create_emulator();
+        }
         let input_state = Arc::new(RwLock::new(InputState::default()));
         Ok(App {
             input,
@@ -82,22 +120,76 @@ impl App {
             mut emulator_view,
             input_state,
         } = self;
+        let mut display = screen::Display::default();
+        let mut gamepads = Gamepads::new();
         event_loop.run(move |event, _, control_flow| {
             // Handle input events
             if input.update(&event) {
                 // Close events
                 if input.key_pressed(VirtualKeyCode::Escape) || input.close_requested() {
+                    // lets the emulator flush its trace log
+                    if let Err(e) = emulator_view.send(EmulatorEvents::QuitEmulator) {
+                        eprintln!("couldn't close the emulator with {e}");
+                    }
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
+                if input.key_pressed(DUMP_MEMORY_KEY) {
+                    if let Some(dir) = paths::dump_dir() {
+                        if let Err(e) = emulator_view.send(EmulatorEvents::DumpMemory(dir)) {
+                            eprintln!("couldn't send event to emulator with {e}");
+                        }
+                    }
+                }
+                if input.key_pressed(SCREENSHOT_KEY) {
+                    framework.gui.screenshot_requested = true;
+                }
+                if input.key_pressed(NEXT_ROM_KEY) {
+                    framework.gui.switch_rom(1);
+                }
+                if input.key_pressed(PREVIOUS_ROM_KEY) {
+                    framework.gui.switch_rom(-1);
+                }
+                if input.key_pressed(PAUSE_KEY) && !framework.gui.key_map.contains(&PAUSE_KEY) {
+                    framework.gui.toggle_pause();
+                }
+                if !framework.gui.key_map.contains(&TURBO_KEY) {
+                    let speed = if input.key_pressed(TURBO_KEY) {
+                        Some(TURBO_SPEED)
+                    } else if input.key_released(TURBO_KEY) {
+                        Some(1.)
+                    } else {
+                        None
+                    };
+                    if let Some(speed) = speed {
+                        if let Err(e) =
+                            emulator_view.send(EmulatorEvents::SetSpeedMultiplier(speed))
+                        {
+                            eprintln!("couldn't send event to emulator with {e}");
+                        }
+                    }
+                }
+                let mut gamepad_keys = 0;
+                if let Some(gamepads) = &mut gamepads {
+                    for button in gamepads.poll() {
+                        if framework.gui.is_rebinding_button() {
+                            framework.gui.rebind_button(button);
+                        }
+                    }
+                    gamepad_keys = gamepads.keys(&framework.gui.button_map);
+                }
                 if let Ok(mut input_state) = input_state.write() {
-                    input_state.update(&input);
+                    input_state.update(&input, &framework.gui.key_map);
+                    input_state.set_gamepad_keys(gamepad_keys);
                     if let EmulatorViewMode::Client(client_view) = &mut emulator_view.mode {
                         let input = input_state.pressed();
-                        send_over_tcp(
-                            &mut client_view.tcp,
-                            &AppEvents::ClientMessage(ClientMessage::KeyInput(input)),
-                        );
+                        if let Ok(mut tcp) = client_view.tcp.lock() {
+                            let message = AppEvents::ClientMessage(ClientMessage::KeyInput(input));
+                            // a lost host is handled by the thread reading from it
+                            if let Err(e) = send_over_tcp(&mut *tcp, &message) {
+                                println!("failed writing with: {e}");
+                            }
+                        }
                     }
                 }
 
@@ -106,8 +198,24 @@ impl App {
                     framework.scale_factor(scale_factor);
                 }
 
+                // Adjust the speed by scrolling over the display
+                let scroll = input.scroll_diff();
+                if scroll != 0. && !framework.wants_pointer_input() {
+                    framework
+                        .gui
+                        .adjust_cpu_hz(scroll.signum() as i32 * CPU_HZ_SCROLL_STEP);
+                }
+
                 // Resize the window
                 if let Some(size) = input.window_resized() {
+                    if framework.gui.snap_to_integer_scale {
+                        let snapped = integer_scaled_size(size, display.resolution());
+                        if snapped != size {
+                            // the resulting resize event resizes the surface
+                            window.set_inner_size(snapped);
+                            return;
+                        }
+                    }
                     emulator_view.on_pixels_mut(|pixels| {
                         if let Err(err) = pixels.resize_surface(size.width, size.height) {
                             eprintln!("pixels.resize_surface {err}");
@@ -122,13 +230,71 @@ impl App {
 
             match event {
                 Event::WindowEvent { event, .. } => {
+                    if let WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(key),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } = event
+                    {
+                        if framework.gui.is_rebinding() {
+                            framework.gui.rebind_key(key);
+                        }
+                    }
+                    if let WindowEvent::Focused(focused) = event {
+                        framework.gui.focus_changed(focused);
+                    }
+                    if let WindowEvent::DroppedFile(path) = &event {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        let title = format!("Chip8 - {name}");
+                        if framework.gui.open_dropped_rom(path.clone()) {
+                            window.set_title(&title);
+                        }
+                    }
                     // Update egui inputs
                     framework.handle_event(&event);
                 }
                 // Draw the current frame
                 Event::RedrawRequested(_) => {
                     // Prepare egui
-                    framework.prepare(&window);
+                    let clip_rect = emulator_view
+                        .on_pixels(|pixels| pixels.context().scaling_renderer.clip_rect())
+                        .unwrap_or_default();
+                    framework.prepare(&window, clip_rect);
+                    if let Some(scale) = framework.gui.requested_scale.take() {
+                        window.set_inner_size(PhysicalSize::new(
+                            screen::SCREEN_WIDTH as u32 * scale,
+                            screen::SCREEN_HEIGHT as u32 * scale,
+                        ));
+                    }
+                    emulator_view.on_pixels_mut(|pixels| {
+                        display.set_palette(pixels, framework.gui.palette);
+                        display.set_persistence(pixels, framework.gui.persistence_frames);
+                        display.set_phosphor(framework.gui.phosphor_fade);
+                        display.decay(pixels);
+                        if display.take_changed() {
+                            if let Some(debugger) = &mut framework.gui.debugger {
+                                debugger.push_frame(pixels.frame(), display.resolution());
+                            }
+                        }
+                    });
+                    if std::mem::take(&mut framework.gui.screenshot_requested) {
+                        let result = match paths::screenshot_dir() {
+                            Some(dir) => emulator_view
+                                .on_pixels(|pixels| {
+                                    screenshot::save(pixels, display.resolution(), &dir)
+                                })
+                                .map(|result| match result {
+                                    Ok(path) => format!("saved screenshot to {path:?}"),
+                                    Err(e) => format!("couldn't save screenshot with {e}"),
+                                }),
+                            None => Some("couldn't determine the screenshot folder".to_string()),
+                        };
+                        framework.gui.screenshot_result = result;
+                    }
                     emulator_view.on_pixels(|pixels| {
                         let render_result =
                             pixels.render_with(|encoder, render_target, context| {
@@ -149,29 +315,32 @@ impl App {
                     });
                 }
                 Event::UserEvent(app_event) => {
-                    if let EmulatorViewMode::Host(host_view) = &mut emulator_view.mode {
-                        send_over_tcp(&mut host_view.tcp, &app_event);
+                    if let EmulatorViewMode::Host(host_view) = &emulator_view.mode {
+                        // clients that can't be reached anymore are dropped
+                        host_view.connection.send(&app_event);
                     }
                     match app_event {
                         AppEvents::Nop => println!("received a nop? :o"),
                         AppEvents::ClearScreen => {
-                            emulator_view.on_pixels_mut(|pixels| {
-                                pixels.frame_mut().fill(0);
-                            });
+                            emulator_view.on_pixels_mut(|pixels| display.clear(pixels));
+                        }
+                        AppEvents::SetResolution(resolution) => {
+                            emulator_view
+                                .on_pixels_mut(|pixels| display.set_resolution(pixels, resolution));
+                            framework.gui.resolution = display.resolution();
+                        }
+                        AppEvents::Scroll(scroll) => {
+                            emulator_view.on_pixels_mut(|pixels| display.scroll(pixels, scroll));
                         }
 
-                        AppEvents::DrawSprite { sprite, x, y } => {
+                        AppEvents::DrawSprite { sprite, x, y, wrap } => {
+                            let options = DrawOptions {
+                                wrap,
+                                mode: framework.gui.draw_mode(),
+                            };
                             emulator_view.on_pixels_mut(|pixels| {
-                                let color = framework.gui.color.to_array();
-                                for (y_delta, sprite_row) in sprite.into_iter().enumerate() {
-                                    screen::set_row(
-                                        pixels,
-                                        x as usize,
-                                        y as usize + y_delta,
-                                        sprite_row,
-                                        color,
-                                    );
-                                }
+                                display
+                                    .draw_sprite(pixels, x as usize, y as usize, &sprite, options)
                             });
                             let result = emulator_view.send(EmulatorEvents::DisplaySynced);
                             if let Err(e) = result {
@@ -181,16 +350,21 @@ impl App {
                         AppEvents::SpawnEmulator {
                             kind,
                             generation,
+                            quirks,
                             debugger,
+                            start_paused,
                             path,
-                            fps,
+                            cpu_hz,
+                            seed,
                         } => {
                             let config = EmulatorConfig::new(
-                                framework.gui.color,
                                 generation,
+                                quirks,
                                 debugger,
+                                start_paused,
                                 path,
-                                fps,
+                                cpu_hz,
+                                seed,
                             );
                             let event_bus = framework.gui.event_bus.clone();
                             let result = spawn_emulator(
@@ -202,6 +376,7 @@ impl App {
                             );
                             if let Err(e) = result {
                                 eprintln!("failed to spawn emulator with {e}");
+                                framework.gui.spawn_error = Some(e.to_string());
                             }
                         }
                         AppEvents::EmulatorEvent(event) => {
@@ -213,19 +388,50 @@ impl App {
                         AppEvents::DebugEmulatorState(state) => {
                             framework.gui.update_debugger(state);
                         }
-                        AppEvents::ClientMessage(client_message) => {
-                            // Client messages get send by clients and are only processed by the host
-                            if !matches!(emulator_view.mode, EmulatorViewMode::Host(_)) {
-                                return;
+                        AppEvents::Rewound(steps) => {
+                            if let Some(debugger) = &mut framework.gui.debugger {
+                                // the restored state is sent again right after
+                                debugger.drop_newest(steps + 1);
+                            }
+                        }
+                        AppEvents::OpcodeProfile { counts, total } => {
+                            if framework.gui.profiler_open {
+                                framework.gui.profile = Some((counts, total));
                             }
-                            match client_message {
-                                ClientMessage::KeyInput(other_input) => {
-                                    if let Ok(mut input) = input_state.write() {
-                                        input.set_client_keys(other_input);
-                                    }
-                                }
+                        }
+                        AppEvents::ReplayFinished => framework.gui.replay_finished(),
+                        AppEvents::RunToMissed(addr) => {
+                            if let Some(debugger) = &mut framework.gui.debugger {
+                                debugger.run_to_missed = Some(addr);
                             }
                         }
+                        AppEvents::ProgramExited => {
+                            framework.gui.program_exited = true;
+                        }
+                        AppEvents::EmulatorError(error) => {
+                            framework.gui.emulator_error = Some(error);
+                        }
+                        AppEvents::ConnectionTested(result) => {
+                            framework.gui.connection_test = Some(match result {
+                                Ok(()) => "connection works".to_string(),
+                                Err(e) => format!("connection failed: {e}"),
+                            });
+                        }
+                        AppEvents::FlagChanged(flag_set) => {
+                            framework.gui.flag_set = flag_set;
+                        }
+                        AppEvents::EmulatorStats(stats) => {
+                            framework.gui.stats = Some(stats);
+                        }
+                        AppEvents::ConnectionStatus(status) => {
+                            framework.gui.connection_status = Some(status);
+                        }
+                        AppEvents::CancelHosting => {
+                            emulator_view.cancel_hosting();
+                            framework.gui.connection_status = None;
+                        }
+                        // client messages are read by the host threads serving each client
+                        AppEvents::ClientMessage(_) => {}
                     }
                 }
                 _ => (),
@@ -242,19 +448,77 @@ pub enum HostIp {
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub enum EmulatorKind {
     Single,
-    Server { ip: HostIp },
-    Client { host_ip: String },
+    Server {
+        ip: HostIp,
+        port: u16,
+    },
+    Client {
+        host_ip: String,
+        port: u16,
+        /// Seconds the client tries to reach the host before giving up.
+        connect_timeout_secs: u64,
+        /// How often the client tries to reconnect after losing the host.
+        reconnect_attempts: u32,
+    },
 }
 impl Display for EmulatorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             EmulatorKind::Single => write!(f, "Singleplayer"),
-            EmulatorKind::Server { ip: _ } => write!(f, "Server"),
-            EmulatorKind::Client { host_ip: _ } => write!(f, "Client"),
+            EmulatorKind::Server { .. } => write!(f, "Server"),
+            EmulatorKind::Client { .. } => write!(f, "Client"),
         }
     }
 }
 
+/// Returns the largest multiple of the display `resolution` that fits into `size`.
+fn integer_scaled_size(size: PhysicalSize<u32>, resolution: Resolution) -> PhysicalSize<u32> {
+    let width = resolution.width() as u32;
+    let height = resolution.height() as u32;
+    let scale = (size.width / width).min(size.height / height).max(1);
+    PhysicalSize::new(width * scale, height * scale)
+}
+
+/// Address a server with the ip listens on.
+fn server_addr(ip: HostIp, port: u16) -> Result<SocketAddr, EmulatorSpawnError> {
+    let ip = match ip {
+        HostIp::Empty => {
+            return Err(EmulatorSpawnError::NoServerIp);
+        }
+        HostIp::NotFound => {
+            return Err(EmulatorSpawnError::NoServerIp);
+        }
+        HostIp::Ip(ip) => ip,
+    };
+    let Ok(ip) = IpAddr::from_str(&ip) else {
+        return Err(EmulatorSpawnError::IpConvertionError(ip));
+    };
+    socket_addr(ip, port)
+}
+/// Address of the host a client connects to.
+fn client_addr(host_ip: String, port: u16) -> Result<SocketAddr, EmulatorSpawnError> {
+    let Ok(ip) = IpAddr::from_str(&host_ip) else {
+        return Err(EmulatorSpawnError::IpConvertionError(host_ip));
+    };
+    socket_addr(ip, port)
+}
+/// Port 0 would let the OS pick a random port, which a client can't know.
+fn socket_addr(ip: IpAddr, port: u16) -> Result<SocketAddr, EmulatorSpawnError> {
+    if port == 0 {
+        return Err(EmulatorSpawnError::InvalidPort);
+    }
+    Ok(SocketAddr::new(ip, port))
+}
+/// Checks that a server could listen or that a client reaches its host, without spawning an emulator.
+pub fn test_connection(kind: EmulatorKind) -> Result<(), EmulatorSpawnError> {
+    match kind {
+        EmulatorKind::Single => Ok(()),
+        EmulatorKind::Server { ip, port } => Ok(EmulatorView::test_host(server_addr(ip, port)?)?),
+        EmulatorKind::Client { host_ip, port, .. } => {
+            Ok(EmulatorView::test_client(client_addr(host_ip, port)?)?)
+        }
+    }
+}
 fn spawn_emulator(
     emulator_view: &mut EmulatorView,
     config: EmulatorConfig,
@@ -267,70 +531,183 @@ fn spawn_emulator(
     if let Err(e) = emulator_view.send(EmulatorEvents::QuitEmulator) {
         println!("couldn't close other emulators with {e}");
     }
-    event_bus
-        .send_event(AppEvents::ClearScreen)
-        .expect("couldn't send event to app");
+    // the new emulator starts in the low resolution, which also clears the screen
+    send_event(&event_bus, AppEvents::SetResolution(Resolution::Low));
     match kind {
         EmulatorKind::Single => {
-            let (view, recv) = EmulatorView::single(Arc::clone(&pixels));
+            let (view, recv) = EmulatorView::single(pixels);
             *emulator_view = view;
             thread::spawn(move || {
-                let chip8 = Chip8::new(event_bus, pixels, input_state, recv, config);
+                let chip8 = Chip8::new(event_bus, input_state, recv, config);
                 chip8.run();
             });
         }
-        EmulatorKind::Server { ip } => {
-            let ip = match ip {
-                HostIp::Empty => {
-                    return Err(EmulatorSpawnError::NoServerIp);
-                }
-                HostIp::NotFound => {
-                    return Err(EmulatorSpawnError::NoServerIp);
-                }
-                HostIp::Ip(ip) => ip,
-            };
-            let Ok(ip) = IpAddr::from_str(&ip) else {
-                return Err(EmulatorSpawnError::IpConvertionError(ip));
-            };
-            let socket_addr = SocketAddr::new(ip, PORT);
-            let (view, recv, mut tcp) = EmulatorView::host(Arc::clone(&pixels), socket_addr)?;
+        EmulatorKind::Server { ip, port } => {
+            let socket_addr = server_addr(ip, port)?;
+            let (view, recv, connection) = EmulatorView::host(pixels, socket_addr)?;
             *emulator_view = view;
-            let event_bus2 = event_bus.clone();
+            send_event(
+                &event_bus,
+                AppEvents::ConnectionStatus(ConnectionStatus::Hosting { clients: 0 }),
+            );
             thread::spawn(move || {
+                // the emulator starts once the first client connected
+                let mut start_emulator = Some({
+                    let event_bus = event_bus.clone();
+                    let input_state = Arc::clone(&input_state);
+                    move || {
+                        let chip8 = Chip8::new(event_bus, input_state, recv, config);
+                        chip8.run();
+                    }
+                });
                 loop {
-                    if let Some(message) = receive_event_over_tcp(&mut tcp) {
-                        // only send messages to the app that are from a client
-                        if matches!(message, AppEvents::ClientMessage(_)) {
-                            event_bus2
-                                .send_event(message)
-                                .expect("couldn't send event to app");
+                    let (slot, mut tcp) = match connection.accept() {
+                        Ok(Some(client)) => client,
+                        // hosting stopped
+                        Ok(None) => return,
+                        Err(e) => {
+                            let e = EmulatorSpawnError::from(e);
+                            eprintln!("failed to spawn emulator with {e}");
+                            send_event(&event_bus, AppEvents::EmulatorError(e.to_string()));
+                            send_event(
+                                &event_bus,
+                                AppEvents::ConnectionStatus(ConnectionStatus::Disconnected),
+                            );
+                            return;
                         }
+                    };
+                    send_event(
+                        &event_bus,
+                        AppEvents::ConnectionStatus(ConnectionStatus::Hosting {
+                            clients: connection.client_count(),
+                        }),
+                    );
+                    if let Some(start_emulator) = start_emulator.take() {
+                        thread::spawn(start_emulator);
                     }
+                    let connection = connection.clone();
+                    let event_bus = event_bus.clone();
+                    let input_state = Arc::clone(&input_state);
+                    thread::spawn(move || {
+                        let e = read_client_input(&mut tcp, slot, &input_state);
+                        println!("lost connection to client {slot} with: {e}");
+                        connection.remove_client(slot);
+                        if let Ok(mut input) = input_state.write() {
+                            input.set_client_keys(slot, 0);
+                        }
+                        if !connection.is_closed() {
+                            send_event(
+                                &event_bus,
+                                AppEvents::ConnectionStatus(ConnectionStatus::Hosting {
+                                    clients: connection.client_count(),
+                                }),
+                            );
+                        }
+                    });
                 }
             });
-            thread::spawn(move || {
-                let chip8 = Chip8::new(event_bus, pixels, input_state, recv, config);
-                chip8.run();
-            });
         }
-        EmulatorKind::Client { host_ip } => {
-            let Ok(ip) = IpAddr::from_str(&host_ip) else {
-                return Err(EmulatorSpawnError::IpConvertionError(host_ip));
-            };
-            let socket_addr = SocketAddr::new(ip, PORT);
-            let (client, mut tcp) = EmulatorView::client(pixels, socket_addr)?;
-            *emulator_view = client;
+        EmulatorKind::Client {
+            host_ip,
+            port,
+            connect_timeout_secs,
+            reconnect_attempts,
+        } => {
+            let socket_addr = client_addr(host_ip, port)?;
+            let timeout = Duration::from_secs(connect_timeout_secs);
+            let (view, mut tcp, client) = EmulatorView::client(pixels, socket_addr, timeout)?;
+            *emulator_view = view;
+            send_event(
+                &event_bus,
+                AppEvents::ConnectionStatus(ConnectionStatus::Connected),
+            );
             thread::spawn(move || loop {
-                if let Some(message) = receive_event_over_tcp(&mut tcp) {
-                    event_bus
-                        .send_event(message)
-                        .expect("couldn't send event to app");
+                let message = match receive_event_over_tcp(&mut tcp) {
+                    Ok(message) => message,
+                    Err(e @ ReceiveError::Incompatible(_)) => {
+                        // reconnecting to the same host won't help
+                        eprintln!("{e}");
+                        send_event(
+                            &event_bus,
+                            AppEvents::ConnectionStatus(ConnectionStatus::Disconnected),
+                        );
+                        return;
+                    }
+                    Err(e @ ReceiveError::DeserializeError(_)) => {
+                        println!("skipping message with: {e}");
+                        continue;
+                    }
+                    // the client was closed on purpose
+                    Err(_) if client.is_closed() => return,
+                    Err(e) => {
+                        println!("failed reading with: {e}");
+                        let reconnected = reconnect(socket_addr, reconnect_attempts, |attempt| {
+                            send_event(
+                                &event_bus,
+                                AppEvents::ConnectionStatus(ConnectionStatus::Reconnecting {
+                                    attempt,
+                                }),
+                            );
+                        });
+                        let Some(new_tcp) = reconnected else {
+                            send_event(
+                                &event_bus,
+                                AppEvents::ConnectionStatus(ConnectionStatus::Disconnected),
+                            );
+                            return;
+                        };
+                        if client.is_closed() {
+                            return;
+                        }
+                        match (new_tcp.try_clone(), client.tcp.lock()) {
+                            (Ok(new_writer), Ok(mut writer)) => *writer = new_writer,
+                            _ => {
+                                eprintln!("couldn't share the new connection with the app");
+                                return;
+                            }
+                        }
+                        tcp = new_tcp;
+                        send_event(&event_bus, AppEvents::ClearScreen);
+                        send_event(
+                            &event_bus,
+                            AppEvents::ConnectionStatus(ConnectionStatus::Connected),
+                        );
+                        continue;
+                    }
+                };
+                if let Err(e) = event_bus.send_event(message) {
+                    eprintln!("couldn't send event to app with {e}");
+                    return;
                 }
             });
         }
     }
     Ok(())
 }
+/// Applies the key inputs of the client in `slot` until the connection is lost.
+///
+/// Returns why the connection was lost.
+fn read_client_input(
+    tcp: &mut TcpStream,
+    slot: usize,
+    input_state: &RwLock<InputState>,
+) -> ReceiveError {
+    loop {
+        match receive_event_over_tcp(tcp) {
+            Ok(AppEvents::ClientMessage(ClientMessage::KeyInput(keys))) => {
+                if let Ok(mut input) = input_state.write() {
+                    input.set_client_keys(slot, keys);
+                }
+            }
+            Ok(_) => {}
+            // the message was read completely, so the next one can still be read
+            Err(e @ ReceiveError::DeserializeError(_)) => {
+                println!("skipping message with: {e}");
+            }
+            Err(e) => return e,
+        }
+    }
+}
 pub fn fetch_global_ip() -> Option<String> {
     let resp = minreq::get("https://api6.ipify.org").send();
     let Ok(resp) = resp else {
@@ -345,6 +722,8 @@ pub fn fetch_global_ip() -> Option<String> {
 pub enum EmulatorSpawnError {
     NoServerIp,
     IpConvertionError(String),
+    /// Port 0 was chosen.
+    InvalidPort,
     IoError(std::io::Error),
 }
 impl Display for EmulatorSpawnError {
@@ -356,6 +735,9 @@ impl Display for EmulatorSpawnError {
             EmulatorSpawnError::IpConvertionError(ip) => {
                 write!(f, "The ip {ip} couldn't be converted to a valid ip addr.")
             }
+            EmulatorSpawnError::InvalidPort => {
+                write!(f, "The port has to be between 1 and {}", u16::MAX)
+            }
             EmulatorSpawnError::IoError(e) => e.fmt(f),
         }
     }
@@ -0,0 +1,46 @@
+use egui::{Color32, Context};
+use winit::event_loop::EventLoopProxy;
+
+use crate::chip8::asm::{assemble, AsmError};
+use crate::chip8::EmulatorEvents;
+use crate::display_bus::{send_event, AppEvents};
+
+/// Editor that assembles the mnemonics of the debugger into a rom and loads it into the running emulator.
+#[derive(Default, Debug)]
+pub struct AsmEditor {
+    source: String,
+    /// Size of the last loaded program, or why it couldn't be assembled.
+    result: Option<Result<usize, AsmError>>,
+}
+impl AsmEditor {
+    pub fn ui(&mut self, ctx: &Context, open: &mut bool, event_bus: &EventLoopProxy<AppEvents>) {
+        egui::Window::new("Assembler").open(open).show(ctx, |ui| {
+            ui.label("one instruction per line, as shown in the debugger. labels end with :, data starts with db");
+            ui.add(
+                egui::TextEdit::multiline(&mut self.source)
+                    .code_editor()
+                    .desired_rows(16)
+                    .hint_text("loop: i = sprite\n      draw at x=r[0],y=r[1] height 5\n      jmp to loop\nsprite: db f0 90 90 90 f0"),
+            );
+            if ui.button("Assemble and load").clicked() {
+                let result = assemble(&self.source);
+                if let Ok(program) = &result {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::LoadRom(program.clone())),
+                    );
+                }
+                self.result = Some(result.map(|program| program.len()));
+            }
+            match &self.result {
+                Some(Ok(len)) => {
+                    ui.label(format!("loaded {len} bytes"));
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e.to_string());
+                }
+                None => {}
+            }
+        });
+    }
+}
@@ -0,0 +1,138 @@
+use egui::{Color32, Context, RichText, ScrollArea, TextStyle};
+
+use crate::chip8::debug_map::map_op;
+use crate::chip8::hardware::MEMORY_SIZE;
+use crate::chip8::rom::PROGRAM_START;
+use crate::display_bus::DebugState;
+
+use super::memory_view::{program_end, PC_COLOR};
+
+/// Background of lines with a breakpoint.
+const BREAKPOINT_COLOR: Color32 = Color32::from_rgb(30, 30, 120);
+
+/// What was done in the listing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DisassemblyAction {
+    ToggledBreakpoint,
+    /// Run until the pc reaches the address.
+    RunTo(u16),
+}
+/// Listing of the program in the memory window reported to the debugger, one instruction per line.
+#[derive(Default, Debug, PartialEq)]
+pub struct DisassemblyView {
+    /// Line the listing scrolls to in the next frame.
+    scroll_to_line: Option<usize>,
+}
+impl DisassemblyView {
+    /// Shows the listing. Clicking a line toggles a breakpoint at its address,
+    /// its context menu runs to it.
+    pub fn ui(
+        &mut self,
+        ctx: &Context,
+        state: &DebugState,
+        breakpoints: &mut Vec<u16>,
+    ) -> Option<DisassemblyAction> {
+        let mut action = None;
+        egui::Window::new("Disassembly").show(ctx, |ui| {
+            let memory = &state.memory[..];
+            let start = state.memory_start as usize;
+            let first = start.max(PROGRAM_START);
+            if start + memory.len() <= first {
+                ui.label("the program is shown after the next step");
+                return;
+            }
+            // trailing zeros are only cut off while the whole memory is reported
+            let end = if memory.len() == MEMORY_SIZE {
+                program_end(memory)
+            } else {
+                start + memory.len()
+            };
+            let lines = disassemble(first, &memory[first - start..end - start]);
+            ui.horizontal(|ui| {
+                ui.colored_label(PC_COLOR, format!("pc: {:04x}", state.pc));
+                if ui.small_button("scroll to pc").clicked() {
+                    let offset = (state.pc as usize).saturating_sub(first);
+                    self.scroll_to_line = Some(offset / 2);
+                }
+            });
+            ui.label("click a line to toggle a breakpoint, right click to run to it");
+            let row_height = ui.text_style_height(&TextStyle::Monospace);
+            let mut scroll_area = ScrollArea::vertical().max_height(400.);
+            if let Some(line) = self.scroll_to_line.take() {
+                let offset = line as f32 * (row_height + ui.spacing().item_spacing.y);
+                scroll_area = scroll_area.vertical_scroll_offset(offset);
+            }
+            scroll_area.show_rows(ui, row_height, lines.len(), |ui, rows| {
+                for (addr, text) in &lines[rows] {
+                    let addr = *addr;
+                    let mut text = RichText::new(text).monospace();
+                    // the pc can be odd, so it's highlighted in the line containing it
+                    if state.pc & !1 == addr & !1 {
+                        text = text.background_color(PC_COLOR);
+                    } else if breakpoints.contains(&addr) {
+                        text = text.background_color(BREAKPOINT_COLOR);
+                    }
+                    let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                    response.clone().context_menu(|ui| {
+                        if ui.button("run to here").clicked() {
+                            action = Some(DisassemblyAction::RunTo(addr));
+                            ui.close_menu();
+                        }
+                    });
+                    if response.clicked() {
+                        match breakpoints.iter().position(|&b| b == addr) {
+                            Some(index) => {
+                                breakpoints.remove(index);
+                            }
+                            None => {
+                                breakpoints.push(addr);
+                                breakpoints.sort_unstable();
+                            }
+                        }
+                        action = Some(DisassemblyAction::ToggledBreakpoint);
+                    }
+                }
+            });
+        });
+        action
+    }
+}
+/// Lines of the listing of `program` with the address of each, in steps of 2 bytes from `start`.
+///
+/// Bytes that aren't an instruction are listed as data.
+fn disassemble(start: usize, program: &[u8]) -> Vec<(u16, String)> {
+    program
+        .chunks(2)
+        .enumerate()
+        .map(|(index, bytes)| {
+            let addr = (start + index * 2) as u16;
+            let text = match *bytes {
+                [high, low] => {
+                    let op = u16::from_be_bytes([high, low]);
+                    match map_op(op) {
+                        mnemonic if mnemonic.is_empty() => format!("db {high:02x} {low:02x}"),
+                        mnemonic => mnemonic,
+                    }
+                }
+                [byte] => format!("db {byte:02x}"),
+                _ => unreachable!("chunks of 2 bytes"),
+            };
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            (addr, format!("{addr:04x}: {hex:4}  {text}"))
+        })
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_opcodes_are_listed_as_data() {
+        let lines = disassemble(PROGRAM_START, &[0x00, 0xe0, 0xff, 0xff, 0x12]);
+        assert_eq!(lines[0], (0x200, "0200: 00e0  clear".to_string()));
+        assert_eq!(lines[1], (0x202, "0202: ffff  db ff ff".to_string()));
+        assert_eq!(lines[2], (0x204, "0204: 12    db 12".to_string()));
+        let lines = disassemble(0x3f0, &[0x00, 0xe0]);
+        assert_eq!(lines[0], (0x3f0, "03f0: 00e0  clear".to_string()));
+    }
+}
@@ -1,9 +1,12 @@
 use std::{
-    io::{Read, Write},
-    net::{SocketAddr, TcpListener, TcpStream},
+    error::Error,
+    fmt::Display,
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, SendError, Sender},
-        Arc, RwLock,
+        Arc, Mutex, RwLock,
     },
     thread,
     time::Duration,
@@ -14,7 +17,9 @@ use winit::window::Window;
 
 use crate::{
     chip8::{screen, EmulatorEvents},
-    display_bus::AppEvents,
+    display_bus::{AppEvents, ClientMessage},
+    format::{self, FormatError},
+    io::MAX_CLIENTS,
 };
 
 use super::EmulatorSpawnError;
@@ -25,7 +30,21 @@ pub enum EmulatorViewMode {
     Single(SingleView),
     OffView(OffView),
 }
-pub const PORT: u16 = 4442;
+/// Port hosts listen on unless another one is chosen.
+pub const DEFAULT_PORT: u16 = 4442;
+/// How long a connection test tries to reach the host.
+const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a host waits for a new connection to identify as a connection test.
+const CONNECTION_TEST_WAIT: Duration = Duration::from_millis(200);
+/// How often a waiting host checks for a new connection.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Default number of seconds a client tries to reach its host.
+pub const CONNECT_TIMEOUT_SECS: u64 = 3;
+/// Default number of reconnection attempts of a client that lost its host.
+pub const RECONNECT_ATTEMPTS: u32 = 5;
+/// Longest message accepted from a peer. Real messages are a few KB at most,
+/// so anything longer comes from a corrupted stream and isn't allocated.
+const MAX_MESSAGE_LEN: u64 = 1 << 20;
 
 pub type PixelRef = Arc<RwLock<Pixels>>;
 pub struct EmulatorView {
@@ -34,23 +53,20 @@ pub struct EmulatorView {
 }
 impl EmulatorView {
     pub fn send(&mut self, event: EmulatorEvents) -> Result<(), SendError<EmulatorEvents>> {
+        if matches!(event, EmulatorEvents::QuitEmulator) {
+            // the threads talking to the peer stop as well
+            match &self.mode {
+                EmulatorViewMode::Host(host) => host.connection.close(),
+                EmulatorViewMode::Client(client) => client.close(),
+                EmulatorViewMode::Single(_) | EmulatorViewMode::OffView(_) => {}
+            }
+        }
         match &self.mode {
             EmulatorViewMode::Host(host) => {
                 host.sender.send(event)?;
             }
-            EmulatorViewMode::Client(_) => {
-                let EmulatorEvents::ChangeColor(new_color) = event else {
-                    return Ok(());
-                };
-                self.on_pixels_mut(|pixels| {
-                    pixels
-                        .frame_mut()
-                        .chunks_mut(4)
-                        .filter(|c| *c != [0, 0, 0, 0])
-                        .for_each(|c| c.clone_from_slice(&new_color.to_array()))
-                })
-            }
-            EmulatorViewMode::OffView(_) => {}
+            // the emulator of a client runs on its host
+            EmulatorViewMode::Client(_) | EmulatorViewMode::OffView(_) => {}
             EmulatorViewMode::Single(single) => {
                 single.sender.send(event)?;
             }
@@ -70,20 +86,27 @@ impl EmulatorView {
             mode: EmulatorViewMode::OffView(OffView {}),
         })
     }
+    /// Connects to the host, giving up after `timeout`.
+    ///
+    /// Returns the view, the stream to read host events from and a copy of the client view
+    /// for replacing the connection after reconnecting.
     pub fn client(
         pixels: PixelRef,
         host_addr: SocketAddr,
-    ) -> Result<(Self, TcpStream), EmulatorSpawnError> {
-        let connection = TcpStream::connect(host_addr)?;
+        timeout: Duration,
+    ) -> Result<(Self, TcpStream, ClientView), EmulatorSpawnError> {
+        let connection = TcpStream::connect_timeout(&host_addr, timeout)?;
         println!("CLIENT connected with {connection:?}");
+        let client = ClientView {
+            tcp: Arc::new(Mutex::new(connection.try_clone()?)),
+            closed: Arc::new(AtomicBool::new(false)),
+        };
         let view = EmulatorView {
             pixels,
-            mode: EmulatorViewMode::Client(ClientView {
-                tcp: connection.try_clone()?,
-            }),
+            mode: EmulatorViewMode::Client(client.clone()),
         };
         thread::sleep(Duration::from_secs_f32(0.05));
-        Ok((view, connection))
+        Ok((view, connection, client))
     }
     pub fn single(pixels: PixelRef) -> (Self, Receiver<EmulatorEvents>) {
         let (sender, recv) = mpsc::channel();
@@ -93,28 +116,48 @@ impl EmulatorView {
         };
         (view, recv)
     }
+    /// Starts listening on `addr` without waiting for clients, which is left to [`HostConnection::accept`].
+    ///
+    /// Events sent to the view before the first client connected are kept for the emulator.
     pub fn host(
         pixels: PixelRef,
         addr: SocketAddr,
-    ) -> Result<(Self, Receiver<EmulatorEvents>, TcpStream), EmulatorSpawnError> {
-        let connection = {
-            let listener = TcpListener::bind(addr)?;
-            println!("start searching");
-            let (connection, addr) = listener.accept()?;
-            println!("connection was successful with: {}", addr);
-            thread::sleep(Duration::from_secs_f32(0.05));
-            connection
-        };
+    ) -> Result<(Self, Receiver<EmulatorEvents>, HostConnection), EmulatorSpawnError> {
+        let connection = HostConnection::default();
+        connection.listen(addr)?;
         let (sender, recv) = mpsc::channel();
-        let connection2 = connection.try_clone()?;
         let view = EmulatorView {
             mode: EmulatorViewMode::Host(HostView {
                 sender,
-                tcp: connection,
+                connection: connection.clone(),
             }),
             pixels,
         };
-        Ok((view, recv, connection2))
+        Ok((view, recv, connection))
+    }
+    /// Stops the host and its emulator, freeing the port.
+    pub fn cancel_hosting(&mut self) {
+        if !matches!(self.mode, EmulatorViewMode::Host(_)) {
+            return;
+        }
+        println!("stopped hosting");
+        if let Err(e) = self.send(EmulatorEvents::QuitEmulator) {
+            println!("couldn't close the emulator with {e}");
+        }
+        self.mode = EmulatorViewMode::OffView(OffView {});
+    }
+    /// Checks that a host could listen on `addr`.
+    pub fn test_host(addr: SocketAddr) -> std::io::Result<()> {
+        TcpListener::bind(addr)?;
+        Ok(())
+    }
+    /// Checks that a host is waiting at `addr`, without joining it.
+    pub fn test_client(addr: SocketAddr) -> std::io::Result<()> {
+        let mut connection = TcpStream::connect_timeout(&addr, CONNECTION_TEST_TIMEOUT)?;
+        send_over_tcp(
+            &mut connection,
+            &AppEvents::ClientMessage(ClientMessage::ConnectionTest),
+        )
     }
     pub fn on_pixels<T>(&self, f: impl FnOnce(&Pixels) -> T) -> Option<T> {
         self.pixels.read().ok().map(|p| f(&p))
@@ -135,33 +178,348 @@ pub struct SingleView {
 }
 pub struct HostView {
     sender: Sender<EmulatorEvents>,
-    pub tcp: TcpStream,
+    pub connection: HostConnection,
+}
+/// Connections of a host to its clients, shared between the view and the threads talking to the clients.
+#[derive(Clone, Default)]
+pub struct HostConnection {
+    /// Listener accepting clients until hosting stops.
+    listener: Arc<Mutex<Option<TcpListener>>>,
+    /// Connected clients with the input slot each of them was given.
+    clients: Arc<Mutex<Vec<(usize, TcpStream)>>>,
+    /// Set once hosting stopped, so no further clients are accepted.
+    closed: Arc<AtomicBool>,
+}
+impl HostConnection {
+    /// Starts accepting clients on `addr`. Does nothing once hosting stopped.
+    pub fn listen(&self, addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        if let Ok(mut slot) = self.listener.lock() {
+            if !self.is_closed() {
+                println!("start searching");
+                *slot = Some(listener);
+            }
+        }
+        Ok(())
+    }
+    /// Waits for the next client, answering connection tests on the way, and shares the connection with the view.
+    /// Clients beyond [`MAX_CLIENTS`] are turned away.
+    ///
+    /// Returns the input slot of the client and the stream to read its messages from, or `None` if hosting stopped.
+    pub fn accept(&self) -> io::Result<Option<(usize, TcpStream)>> {
+        loop {
+            let accepted = match self.listener.lock() {
+                Ok(listener) => match listener.as_ref() {
+                    Some(listener) => listener.accept(),
+                    None => return Ok(None),
+                },
+                Err(_) => return Ok(None),
+            };
+            let (mut connection, addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => {
+                    // frees the port
+                    self.listener.lock().ok().and_then(|mut l| l.take());
+                    return Err(e);
+                }
+            };
+            // the connection may inherit the non-blocking mode of the listener
+            connection.set_nonblocking(false)?;
+            if is_connection_test(&mut connection) {
+                println!("answered connection test from: {addr}");
+                continue;
+            }
+            let Ok(mut clients) = self.clients.lock() else {
+                return Ok(None);
+            };
+            // clients may connect right before hosting stops
+            if self.is_closed() {
+                return Ok(None);
+            }
+            let free_slot = (0..MAX_CLIENTS).find(|slot| clients.iter().all(|(s, _)| s != slot));
+            let Some(slot) = free_slot else {
+                println!("turned away {addr}, already hosting {MAX_CLIENTS} clients");
+                if let Err(e) = connection.shutdown(Shutdown::Both) {
+                    println!("couldn't shut down the connection with: {e}");
+                }
+                continue;
+            };
+            println!("connection was successful with: {addr}");
+            let reader = connection.try_clone()?;
+            clients.push((slot, connection));
+            return Ok(Some((slot, reader)));
+        }
+    }
+    /// Sends `event` to all clients. Clients that can't be written to are disconnected.
+    pub fn send(&self, event: &AppEvents) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        clients.retain_mut(|(slot, tcp)| match send_over_tcp(tcp, event) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("dropping client {slot} after failing to send with: {e}");
+                // also stops the thread reading from it
+                if let Err(e) = tcp.shutdown(Shutdown::Both) {
+                    println!("couldn't shut down the connection with: {e}");
+                }
+                false
+            }
+        });
+    }
+    /// Forgets the client in `slot` after its connection was lost, freeing the slot for the next client.
+    pub fn remove_client(&self, slot: usize) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|(s, _)| *s != slot);
+        }
+    }
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().map_or(0, |clients| clients.len())
+    }
+    /// Stops accepting clients and disconnects all of them for good.
+    pub fn close(&self) {
+        if let Ok(mut listener) = self.listener.lock() {
+            self.closed.store(true, Ordering::Relaxed);
+            listener.take();
+        }
+        let clients = self
+            .clients
+            .lock()
+            .map(|mut clients| std::mem::take(&mut *clients))
+            .unwrap_or_default();
+        for (_, tcp) in clients {
+            if let Err(e) = tcp.shutdown(Shutdown::Both) {
+                println!("couldn't shut down the connection with: {e}");
+            }
+        }
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
 }
-impl HostView {}
+/// A tcp stream that gets replaced when the client reconnects.
+pub type SharedTcp = Arc<Mutex<TcpStream>>;
+#[derive(Clone)]
 pub struct ClientView {
-    pub tcp: TcpStream,
+    pub tcp: SharedTcp,
+    /// Set once the client stopped, so a lost host isn't reconnected to.
+    closed: Arc<AtomicBool>,
+}
+impl ClientView {
+    /// Disconnects from the host for good.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Ok(tcp) = self.tcp.lock() {
+            if let Err(e) = tcp.shutdown(Shutdown::Both) {
+                println!("couldn't shut down the connection with: {e}");
+            }
+        }
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
 }
-pub fn send_over_tcp(tcp: &mut TcpStream, event: &AppEvents) {
-    let bytes = bincode::serialize(event);
-    let Ok(mut bytes) = bytes else { return };
-    let mut buffer = bytes.len().to_be_bytes().to_vec();
+/// Writes `event` as one message. Fails if the peer dropped the connection.
+pub fn send_over_tcp(tcp: &mut impl Write, event: &AppEvents) -> io::Result<()> {
+    let mut bytes = format::serialize(event).map_err(io::Error::other)?;
+    let mut buffer = format::header().to_vec();
+    // a fixed size length, usize differs between platforms
+    buffer.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
     buffer.append(&mut bytes);
+    tcp.write_all(&buffer)?;
+    tcp.flush()
+}
+/// Waits briefly for the first message of a new connection to tell connection tests from clients.
+fn is_connection_test(tcp: &mut TcpStream) -> bool {
+    if let Err(e) = tcp.set_read_timeout(Some(CONNECTION_TEST_WAIT)) {
+        println!("couldn't wait for a connection test with: {e}");
+        return false;
+    }
+    let message = receive_event_over_tcp(tcp);
+    if let Err(e) = tcp.set_read_timeout(None) {
+        println!("couldn't reset the read timeout with: {e}");
+    }
+    matches!(
+        message,
+        Ok(AppEvents::ClientMessage(ClientMessage::ConnectionTest))
+    )
+}
+/// Tries to connect to `addr` up to `attempts` times, doubling the wait between attempts.
+/// `on_attempt` is called with the number of the attempt before each try.
+pub fn reconnect(addr: SocketAddr, attempts: u32, on_attempt: impl Fn(u32)) -> Option<TcpStream> {
+    let mut backoff = Duration::from_millis(250);
+    for attempt in 1..=attempts {
+        on_attempt(attempt);
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(2)) {
+            Ok(connection) => {
+                println!("CLIENT reconnected with {connection:?}");
+                return Some(connection);
+            }
+            Err(e) => println!("reconnect attempt {attempt} failed with: {e}"),
+        }
+        thread::sleep(backoff);
+        backoff *= 2;
+    }
+    None
+}
+#[derive(Debug)]
+pub enum ReceiveError {
+    IoError(std::io::Error),
+    /// The peer runs a build with another serialization format.
+    Incompatible(FormatError),
+    DeserializeError(bincode::Error),
+    /// The message is longer than [`MAX_MESSAGE_LEN`].
+    MessageTooLong(u64),
+}
+impl Display for ReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiveError::IoError(e) => e.fmt(f),
+            ReceiveError::Incompatible(e) => write!(f, "peer runs incompatible version: {e}"),
+            ReceiveError::DeserializeError(e) => write!(f, "couldn't deserialize message: {e}"),
+            ReceiveError::MessageTooLong(length) => {
+                write!(
+                    f,
+                    "message of {length} bytes exceeds {MAX_MESSAGE_LEN} bytes"
+                )
+            }
+        }
+    }
+}
+impl Error for ReceiveError {}
+impl From<std::io::Error> for ReceiveError {
+    fn from(value: std::io::Error) -> Self {
+        ReceiveError::IoError(value)
+    }
+}
+impl From<FormatError> for ReceiveError {
+    fn from(value: FormatError) -> Self {
+        ReceiveError::Incompatible(value)
+    }
+}
+impl From<bincode::Error> for ReceiveError {
+    fn from(value: bincode::Error) -> Self {
+        ReceiveError::DeserializeError(value)
+    }
+}
+pub fn receive_event_over_tcp(tcp: &mut impl Read) -> Result<AppEvents, ReceiveError> {
+    let mut header = format::header();
+    tcp.read_exact(&mut header)?;
+    format::check_header(&header)?;
+    let mut length_bytes = 0u64.to_be_bytes();
+    tcp.read_exact(&mut length_bytes)?;
+    let length = u64::from_be_bytes(length_bytes);
+    if length > MAX_MESSAGE_LEN {
+        return Err(ReceiveError::MessageTooLong(length));
+    }
+    let mut message = vec![0; length as usize];
+    tcp.read_exact(&mut message)?;
+    let message: AppEvents = format::deserialize(&message)?;
+    Ok(message)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    tcp.write_all(&buffer).unwrap();
-    tcp.flush().unwrap();
-}
-pub fn receive_event_over_tcp(tcp: &mut TcpStream) -> Option<AppEvents> {
-    let mut length_bytes = 0usize.to_be_bytes();
-    if let Err(e) = tcp.read_exact(&mut length_bytes) {
-        println!("failed reading with: {e}");
-        return None;
-    };
-    let length = usize::from_be_bytes(length_bytes);
-    let mut message = vec![0; length];
-    if let Err(e) = tcp.read_exact(&mut message) {
-        println!("failed reading with: {e}");
-        return None;
-    };
-    let message: AppEvents = bincode::deserialize(&message).unwrap();
-    Some(message)
+    #[test]
+    fn events_round_trip() {
+        let event = AppEvents::DrawSprite {
+            sprite: vec![0xf0; 16],
+            x: 12,
+            y: 30,
+            wrap: true,
+        };
+        let mut pipe = Vec::new();
+        send_over_tcp(&mut pipe, &event).unwrap();
+        send_over_tcp(&mut pipe, &AppEvents::ClearScreen).unwrap();
+        let mut reader = &pipe[..];
+        assert_eq!(receive_event_over_tcp(&mut reader).unwrap(), event);
+        assert_eq!(
+            receive_event_over_tcp(&mut reader).unwrap(),
+            AppEvents::ClearScreen
+        );
+        assert!(reader.is_empty());
+    }
+    #[test]
+    fn malformed_messages_are_skipped() {
+        let mut pipe = format::header().to_vec();
+        pipe.extend_from_slice(&2u64.to_be_bytes());
+        pipe.extend_from_slice(&[0xff, 0xff]);
+        send_over_tcp(&mut pipe, &AppEvents::ClearScreen).unwrap();
+        let mut reader = &pipe[..];
+        assert!(matches!(
+            receive_event_over_tcp(&mut reader),
+            Err(ReceiveError::DeserializeError(_))
+        ));
+        assert_eq!(
+            receive_event_over_tcp(&mut reader).unwrap(),
+            AppEvents::ClearScreen
+        );
+    }
+    fn host_connection() -> (HostConnection, SocketAddr) {
+        let connection = HostConnection::default();
+        connection.listen("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = connection
+            .listener
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .local_addr();
+        (connection, addr.unwrap())
+    }
+    fn accept(connection: &HostConnection, addr: SocketAddr) -> (usize, TcpStream) {
+        let waiting = thread::spawn({
+            let connection = connection.clone();
+            move || connection.accept()
+        });
+        let client = TcpStream::connect(addr).unwrap();
+        let (slot, _) = waiting.join().unwrap().unwrap().unwrap();
+        (slot, client)
+    }
+    #[test]
+    fn accepts_several_clients() {
+        let (connection, addr) = host_connection();
+        let (first_slot, mut first) = accept(&connection, addr);
+        let (second_slot, mut second) = accept(&connection, addr);
+        assert_eq!((first_slot, second_slot), (0, 1));
+        connection.send(&AppEvents::ClearScreen);
+        for client in [&mut first, &mut second] {
+            assert_eq!(
+                receive_event_over_tcp(client).unwrap(),
+                AppEvents::ClearScreen
+            );
+        }
+        // the slot of a lost client is given to the next one
+        connection.remove_client(first_slot);
+        assert_eq!(connection.client_count(), 1);
+        assert_eq!(accept(&connection, addr).0, first_slot);
+    }
+    #[test]
+    fn closed_host_stops_waiting() {
+        let (connection, addr) = host_connection();
+        let waiting = thread::spawn({
+            let connection = connection.clone();
+            move || connection.accept()
+        });
+        connection.close();
+        assert!(waiting.join().unwrap().unwrap().is_none());
+        // no clients are accepted after closing
+        connection.listen(addr).unwrap();
+        assert!(connection.listener.lock().unwrap().is_none());
+    }
+    #[test]
+    fn rejects_oversized_messages() {
+        let mut pipe = format::header().to_vec();
+        pipe.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            receive_event_over_tcp(&mut &pipe[..]),
+            Err(ReceiveError::MessageTooLong(u64::MAX))
+        ));
+    }
 }
@@ -0,0 +1,321 @@
+use std::fs;
+
+use egui::{Color32, Context, RichText, ScrollArea, TextStyle};
+
+use crate::chip8::rom::PROGRAM_START;
+use crate::display_bus::DebugState;
+
+/// Bytes shown per row of the hex view.
+const BYTES_PER_ROW: usize = 16;
+/// Background of the instruction at the pc.
+pub(super) const PC_COLOR: Color32 = Color32::from_rgb(120, 30, 30);
+/// Background of the byte the index register points to.
+const I_COLOR: Color32 = Color32::from_rgb(110, 80, 0);
+
+/// Hex view of the memory window reported to the debugger, with a search for byte patterns.
+#[derive(Default, Debug, PartialEq)]
+pub struct MemoryView {
+    /// Pattern of hex bytes to search for, `??` matches any byte.
+    pattern: String,
+    /// Offsets of all matches of the pattern into the reported memory.
+    hits: Vec<usize>,
+    /// Match that was jumped to last.
+    selected_hit: Option<usize>,
+    /// Row of the reported memory the hex view scrolls to in the next frame.
+    scroll_to_row: Option<usize>,
+    /// Hex address the exported rom ends at, exclusive. Detected from the memory if empty.
+    export_end: String,
+    /// Address of the byte being edited and the hex value typed for it.
+    editing: Option<(usize, String)>,
+}
+impl MemoryView {
+    /// Shows the memory. Clicking a byte edits it.
+    ///
+    /// Returns the address and the new value of an edited byte.
+    pub fn ui(&mut self, ctx: &Context, state: &DebugState) -> Option<(u16, u8)> {
+        let memory = &state.memory[..];
+        let start = state.memory_start as usize;
+        let mut edit = None;
+        egui::Window::new("Memory").show(ctx, |ui| {
+            if memory.is_empty() {
+                ui.label("the memory is shown after the next step");
+                return;
+            }
+            let window = start..start + memory.len();
+            ui.horizontal(|ui| {
+                for (name, addr, color) in [("pc", state.pc, PC_COLOR), ("i", state.i, I_COLOR)] {
+                    let addr = addr as usize;
+                    ui.colored_label(color, format!("{name}: {addr:04x}"));
+                    let button = egui::Button::new("go to").small();
+                    if ui.add_enabled(window.contains(&addr), button).clicked() {
+                        self.scroll_to_row = Some((addr - start) / BYTES_PER_ROW);
+                    }
+                }
+            });
+            // a rom can only be exported while the whole memory is reported
+            if start == 0 && memory.len() > PROGRAM_START {
+                self.export_ui(ui, memory);
+                ui.separator();
+            }
+            ui.label("click a byte to change it while the cpu is stopped");
+            if self
+                .editing
+                .as_ref()
+                .is_some_and(|(addr, _)| *addr < PROGRAM_START)
+            {
+                ui.colored_label(Color32::YELLOW, "bytes below 200 hold the font");
+            }
+            let pattern = parse_pattern(&self.pattern);
+            ui.horizontal(|ui| {
+                ui.label("find");
+                ui.text_edit_singleline(&mut self.pattern)
+                    .on_hover_text("hex bytes like \"a2 ?? 60\", ?? matches any byte");
+            });
+            let Some(pattern) = pattern else {
+                ui.colored_label(Color32::RED, "pattern has to be hex bytes or ??");
+                self.hits.clear();
+                edit = self.show_memory(ui, state, 0);
+                return;
+            };
+            self.hits = find(memory, &pattern);
+            ui.horizontal(|ui| {
+                ui.label(format!("{} matches", self.hits.len()));
+                let enabled = !self.hits.is_empty();
+                if ui
+                    .add_enabled(enabled, egui::Button::new("previous"))
+                    .clicked()
+                {
+                    self.step_hit(-1);
+                }
+                if ui.add_enabled(enabled, egui::Button::new("next")).clicked() {
+                    self.step_hit(1);
+                }
+                if let Some(hit) = self.selected_hit.and_then(|i| self.hits.get(i)) {
+                    ui.label(format!("at {:03x}", start + hit));
+                }
+            });
+            edit = self.show_memory(ui, state, pattern.len());
+        });
+        edit
+    }
+    /// Exports the program region of the memory as a rom, including changes made while running.
+    fn export_ui(&mut self, ui: &mut egui::Ui, memory: &[u8]) {
+        ui.horizontal(|ui| {
+            ui.label("rom end");
+            ui.add(egui::TextEdit::singleline(&mut self.export_end).desired_width(40.))
+                .on_hover_text("end address in hex, leave empty to cut off trailing zeros");
+            let end = if self.export_end.trim().is_empty() {
+                Some(program_end(memory))
+            } else {
+                usize::from_str_radix(self.export_end.trim(), 16)
+                    .ok()
+                    .filter(|end| (PROGRAM_START..=memory.len()).contains(end))
+            };
+            let Some(end) = end else {
+                ui.colored_label(Color32::RED, "end has to be between 200 and 1000");
+                return;
+            };
+            if ui.button("Export ROM from memory").clicked() {
+                let Some(path) = rfd::FileDialog::new()
+                    .add_filter("chip8 rom", &["ch8"])
+                    .save_file()
+                else {
+                    return;
+                };
+                if let Err(e) = fs::write(&path, &memory[PROGRAM_START..end]) {
+                    eprintln!("couldn't export rom to {path:?} with {e}");
+                }
+            }
+            ui.label(format!("{} bytes", end - PROGRAM_START));
+        });
+    }
+    /// Selects the match `delta` matches away from the selected one and scrolls to it.
+    fn step_hit(&mut self, delta: isize) {
+        let len = self.hits.len() as isize;
+        let index = match self.selected_hit {
+            Some(index) => (index as isize + delta).rem_euclid(len) as usize,
+            None if delta < 0 => self.hits.len() - 1,
+            None => 0,
+        };
+        self.selected_hit = Some(index);
+        self.scroll_to_row = Some(self.hits[index] / BYTES_PER_ROW);
+    }
+    /// Shows the memory as rows of hex bytes followed by their ascii characters.
+    /// Highlights the `pattern_len` bytes of every match, the instruction at the pc and the byte at i.
+    ///
+    /// Returns the address and the new value once an edit is confirmed with enter.
+    fn show_memory(
+        &mut self,
+        ui: &mut egui::Ui,
+        state: &DebugState,
+        pattern_len: usize,
+    ) -> Option<(u16, u8)> {
+        let mut edit = None;
+        let memory = &state.memory[..];
+        let memory_start = state.memory_start as usize;
+        let mut highlighted = vec![false; memory.len()];
+        for &hit in &self.hits {
+            highlighted[hit..hit + pattern_len].fill(true);
+        }
+        let selected = self
+            .selected_hit
+            .and_then(|i| self.hits.get(i))
+            .map(|&hit| hit..hit + pattern_len);
+        let pc = state.pc as usize..state.pc as usize + 2;
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll_area = ScrollArea::vertical().max_height(400.);
+        if let Some(row) = self.scroll_to_row.take() {
+            let offset = row as f32 * (row_height + ui.spacing().item_spacing.y);
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+        let rows = memory.len().div_ceil(BYTES_PER_ROW);
+        scroll_area.show_rows(ui, row_height, rows, |ui, rows| {
+            for row in rows {
+                let start = row * BYTES_PER_ROW;
+                let end = (start + BYTES_PER_ROW).min(memory.len());
+                let bytes = &memory[start..end];
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.;
+                    let row_addr = memory_start + start;
+                    ui.label(RichText::new(format!("{row_addr:03x}:")).monospace());
+                    for (offset, byte) in (start..end).zip(bytes) {
+                        let addr = memory_start + offset;
+                        if let Some((_, value)) = self
+                            .editing
+                            .as_mut()
+                            .filter(|(editing, _)| *editing == addr)
+                        {
+                            let response = ui.add(
+                                egui::TextEdit::singleline(value)
+                                    .desired_width(16.)
+                                    .font(TextStyle::Monospace),
+                            );
+                            response.request_focus();
+                            if response.lost_focus() {
+                                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                    if let Ok(value) = u8::from_str_radix(value.trim(), 16) {
+                                        edit = Some((addr as u16, value));
+                                    }
+                                }
+                                self.editing = None;
+                            }
+                            continue;
+                        }
+                        let text = RichText::new(format!("{byte:02x}")).monospace();
+                        let background =
+                            if selected.as_ref().is_some_and(|hit| hit.contains(&offset)) {
+                                Some(Color32::DARK_GREEN)
+                            } else if highlighted[offset] {
+                                Some(Color32::DARK_BLUE)
+                            } else if pc.contains(&addr) {
+                                Some(PC_COLOR)
+                            } else if addr == state.i as usize {
+                                Some(I_COLOR)
+                            } else {
+                                None
+                            };
+                        let text = match background {
+                            Some(color) => text.background_color(color),
+                            None => text,
+                        };
+                        if ui
+                            .add(egui::Label::new(text).sense(egui::Sense::click()))
+                            .clicked()
+                        {
+                            self.editing = Some((addr, format!("{byte:02x}")));
+                        }
+                    }
+                    ui.label(RichText::new(ascii(bytes)).monospace());
+                });
+            }
+        });
+        edit
+    }
+}
+/// Shows the printable ascii characters of `bytes` and a dot for every other byte.
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+/// End of the program in `memory`, after the last non-zero byte behind [`PROGRAM_START`].
+pub(super) fn program_end(memory: &[u8]) -> usize {
+    memory
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(PROGRAM_START, |last| last + 1)
+        .max(PROGRAM_START)
+}
+/// Parses hex bytes into a search pattern, where `??` matches any byte.
+/// Whitespace between bytes is optional.
+///
+/// Returns `None` if the text isn't made of whole hex bytes and wildcards.
+fn parse_pattern(text: &str) -> Option<Vec<Option<u8>>> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| match pair {
+            ['?', '?'] => Some(None),
+            [high, low] => {
+                let byte = (high.to_digit(16)? << 4) | low.to_digit(16)?;
+                Some(Some(byte as u8))
+            }
+            _ => None,
+        })
+        .collect()
+}
+/// Start addresses of all occurrences of `pattern` in `memory`. Matches may overlap.
+fn find(memory: &[u8], pattern: &[Option<u8>]) -> Vec<usize> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| {
+            window
+                .iter()
+                .zip(pattern)
+                .all(|(byte, expected)| expected.is_none_or(|expected| expected == *byte))
+        })
+        .map(|(addr, _)| addr)
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_patterns_with_wildcards() {
+        let memory = [0xa2, 0x1e, 0x60, 0xa2, 0x00, 0x60, 0xa2];
+        let pattern = parse_pattern("a2 ??60").unwrap();
+        assert_eq!(pattern, vec![Some(0xa2), None, Some(0x60)]);
+        assert_eq!(find(&memory, &pattern), vec![0, 3]);
+        assert_eq!(find(&memory, &[]), Vec::<usize>::new());
+    }
+    #[test]
+    fn program_end_trims_trailing_zeros() {
+        let mut memory = [0; 4096];
+        assert_eq!(program_end(&memory), PROGRAM_START);
+        memory[0x50] = 0xf0;
+        assert_eq!(program_end(&memory), PROGRAM_START);
+        memory[0x203] = 0x12;
+        assert_eq!(program_end(&memory), 0x204);
+    }
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert_eq!(parse_pattern("a2 6"), None);
+        assert_eq!(parse_pattern("g0"), None);
+        assert_eq!(parse_pattern("?0"), None);
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The roms of a folder, cycled through with the next and previous rom keys.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Playlist {
+    roms: Vec<PathBuf>,
+    index: usize,
+}
+impl Playlist {
+    /// Collects the `.ch8` files in `dir`, sorted by name.
+    pub fn from_dir(dir: &Path) -> io::Result<Playlist> {
+        let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("ch8"))
+            })
+            .collect();
+        roms.sort();
+        Ok(Playlist { roms, index: 0 })
+    }
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+    pub fn current(&self) -> Option<&Path> {
+        self.roms.get(self.index).map(PathBuf::as_path)
+    }
+    /// Moves `delta` roms forward, wrapping around at both ends.
+    pub fn step(&mut self, delta: isize) {
+        if self.roms.is_empty() {
+            return;
+        }
+        let len = self.roms.len() as isize;
+        self.index = (self.index as isize + delta).rem_euclid(len) as usize;
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::paths;
+
+/// Number of roms kept in the list.
+const MAX_RECENT_ROMS: usize = 8;
+
+/// The most recently opened roms, newest first, kept in a file of the config directory.
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
+pub struct RecentRoms {
+    roms: Vec<PathBuf>,
+}
+impl RecentRoms {
+    /// Parses the list with one path per line, dropping roms that don't exist anymore.
+    fn parse(text: &str) -> RecentRoms {
+        let roms = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+            .take(MAX_RECENT_ROMS)
+            .collect();
+        RecentRoms { roms }
+    }
+    /// Loads the list from the config directory. Starts empty if there's none yet.
+    pub fn load() -> RecentRoms {
+        let Some(path) = paths::recent_roms() else {
+            return RecentRoms::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => RecentRoms::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => RecentRoms::default(),
+            Err(e) => {
+                eprintln!("couldn't read recent roms {path:?} with {e}");
+                RecentRoms::default()
+            }
+        }
+    }
+    fn save(&self) {
+        let Some(path) = paths::recent_roms() else {
+            return;
+        };
+        let text: String = self
+            .roms
+            .iter()
+            .map(|rom| format!("{}\n", rom.display()))
+            .collect();
+        if let Err(e) = fs::write(&path, text) {
+            eprintln!("couldn't write recent roms {path:?} with {e}");
+        }
+    }
+    pub fn roms(&self) -> &[PathBuf] {
+        &self.roms
+    }
+    /// Moves `rom` to the front of the list and saves it.
+    pub fn push(&mut self, rom: &Path) {
+        self.insert(rom.canonicalize().unwrap_or_else(|_| rom.to_path_buf()));
+        self.save();
+    }
+    fn insert(&mut self, rom: PathBuf) {
+        self.roms.retain(|other| *other != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT_ROMS);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_rom_moves_to_the_front() {
+        let mut recent = RecentRoms::default();
+        for i in 0..10 {
+            recent.insert(PathBuf::from(format!("{i}.ch8")));
+        }
+        recent.insert(PathBuf::from("5.ch8"));
+        assert_eq!(recent.roms().len(), MAX_RECENT_ROMS);
+        assert_eq!(recent.roms()[0], PathBuf::from("5.ch8"));
+        assert_eq!(recent.roms()[1], PathBuf::from("9.ch8"));
+        assert_eq!(
+            recent
+                .roms()
+                .iter()
+                .filter(|rom| rom.ends_with("5.ch8"))
+                .count(),
+            1
+        );
+    }
+    #[test]
+    fn missing_roms_are_dropped() {
+        let rom = std::env::current_exe().unwrap();
+        let text = format!("/no/such/rom.ch8\n\n{}\n", rom.display());
+        assert_eq!(RecentRoms::parse(&text).roms(), [rom]);
+    }
+}
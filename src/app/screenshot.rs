@@ -0,0 +1,87 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{ImageError, RgbaImage};
+use pixels::Pixels;
+
+use crate::chip8::screen::Resolution;
+
+/// Width of a screenshot, the display is scaled up by whole pixels to fill it.
+const SCREENSHOT_WIDTH: u32 = 512;
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    /// The frame doesn't have the size of the resolution.
+    SizeMismatch,
+    ImageError(ImageError),
+}
+impl Display for ScreenshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScreenshotError::SizeMismatch => {
+                write!(f, "the frame doesn't match the display resolution")
+            }
+            ScreenshotError::ImageError(e) => e.fmt(f),
+        }
+    }
+}
+impl Error for ScreenshotError {}
+impl From<ImageError> for ScreenshotError {
+    fn from(value: ImageError) -> Self {
+        ScreenshotError::ImageError(value)
+    }
+}
+
+/// Writes the current frame to a png in `dir`, named after the time it was taken.
+///
+/// The frame is read in the chip8 `resolution`, independent of the size of the window.
+pub fn save(
+    pixels: &Pixels,
+    resolution: Resolution,
+    dir: &Path,
+) -> Result<PathBuf, ScreenshotError> {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("screenshot_{millis}.png"));
+    let scale = SCREENSHOT_WIDTH / resolution.width() as u32;
+    scale_frame(pixels.frame(), resolution, scale)?.save(&path)?;
+    Ok(path)
+}
+/// Scales the RGBA `frame` of the `resolution` up by `scale` in both directions.
+fn scale_frame(
+    frame: &[u8],
+    resolution: Resolution,
+    scale: u32,
+) -> Result<RgbaImage, ScreenshotError> {
+    let (width, height) = (resolution.width() as u32, resolution.height() as u32);
+    let image =
+        RgbaImage::from_raw(width, height, frame.to_vec()).ok_or(ScreenshotError::SizeMismatch)?;
+    Ok(RgbaImage::from_fn(width * scale, height * scale, |x, y| {
+        *image.get_pixel(x / scale, y / scale)
+    }))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_scale_by_whole_pixels() {
+        let mut frame = vec![0; 64 * 32 * 4];
+        frame[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        let image = scale_frame(&frame, Resolution::Low, 8).unwrap();
+        assert_eq!(image.dimensions(), (512, 256));
+        assert_eq!(image.get_pixel(8, 7).0, [1, 2, 3, 4]);
+        assert_eq!(image.get_pixel(15, 0).0, [1, 2, 3, 4]);
+        assert_eq!(image.get_pixel(16, 0).0, [0; 4]);
+        assert!(matches!(
+            scale_frame(&frame, Resolution::High, 4),
+            Err(ScreenshotError::SizeMismatch)
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chip8::clock::DEFAULT_CPU_HZ;
+use crate::chip8::hardware::{Generation, Quirks};
+use crate::chip8::screen::{Palette, PalettePreset};
+use crate::chip8::{DEFAULT_MEMORY_WINDOW, DEFAULT_REWIND_DEPTH};
+use crate::paths;
+
+use super::ui::DEFAULT_DEBUG_HISTORY_LIMIT;
+use super::EmulatorKind;
+
+/// Settings of the gui that are kept between runs.
+/// Settings missing from the file keep their defaults, so older files stay readable.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub generation: Generation,
+    pub quirks: Quirks,
+    pub cpu_hz: u32,
+    pub emulator_kind: EmulatorKind,
+    pub use_builtin_program: bool,
+    pub start_debugger: bool,
+    pub start_paused: bool,
+    pub debug_history_limit: usize,
+    pub rewind_depth: usize,
+    pub memory_window: usize,
+    pub palette_preset: PalettePreset,
+    pub palette: Palette,
+    pub show_grid: bool,
+    pub show_grid_labels: bool,
+    pub show_flag_indicator: bool,
+    pub show_speed: bool,
+    pub muted: bool,
+    pub pause_on_focus_loss: bool,
+    pub snap_to_integer_scale: bool,
+    pub persistence_frames: u8,
+    pub phosphor_fade: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            generation: Generation::default(),
+            quirks: Quirks::default(),
+            cpu_hz: DEFAULT_CPU_HZ,
+            emulator_kind: EmulatorKind::Single,
+            use_builtin_program: false,
+            start_debugger: false,
+            start_paused: false,
+            debug_history_limit: DEFAULT_DEBUG_HISTORY_LIMIT,
+            rewind_depth: DEFAULT_REWIND_DEPTH,
+            memory_window: DEFAULT_MEMORY_WINDOW,
+            palette_preset: PalettePreset::default(),
+            palette: Palette::default(),
+            show_grid: false,
+            show_grid_labels: false,
+            show_flag_indicator: false,
+            show_speed: false,
+            muted: false,
+            pause_on_focus_loss: true,
+            snap_to_integer_scale: false,
+            persistence_frames: 0,
+            phosphor_fade: false,
+        }
+    }
+}
+impl Settings {
+    /// Loads the settings from the config directory.
+    ///
+    /// Falls back to the defaults if there are no settings yet or they can't be read.
+    pub fn load() -> Settings {
+        let Some(path) = paths::settings() else {
+            return Settings::default();
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Settings::default(),
+            Err(e) => {
+                eprintln!("couldn't read settings {path:?} with {e}");
+                return Settings::default();
+            }
+        };
+        toml::from_str(&text).unwrap_or_else(|e| {
+            eprintln!("couldn't parse settings {path:?} with {e}");
+            Settings::default()
+        })
+    }
+    pub fn save(&self) {
+        let Some(path) = paths::settings() else {
+            return;
+        };
+        let text = match toml::to_string(self) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("couldn't serialize settings with {e}");
+                return;
+            }
+        };
+        if let Err(e) = fs::write(&path, text) {
+            eprintln!("couldn't write settings {path:?} with {e}");
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_survive_toml() {
+        let settings = Settings {
+            generation: Generation::Cosmac,
+            quirks: Quirks::for_generation(Generation::Cosmac),
+            cpu_hz: 1000,
+            emulator_kind: EmulatorKind::Client {
+                host_ip: "127.0.0.1".into(),
+                port: 1234,
+                connect_timeout_secs: 5,
+                reconnect_attempts: 3,
+            },
+            palette_preset: PalettePreset::Custom,
+            persistence_frames: 4,
+            ..Default::default()
+        };
+        let text = toml::to_string(&settings).unwrap();
+        assert_eq!(toml::from_str::<Settings>(&text).unwrap(), settings);
+    }
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        let settings: Settings = toml::from_str("cpu_hz = 900").unwrap();
+        assert_eq!(settings.cpu_hz, 900);
+        assert_eq!(settings.rewind_depth, DEFAULT_REWIND_DEPTH);
+        assert!(toml::from_str::<Settings>("cpu_hz = \"fast\"").is_err());
+    }
+}
@@ -1,18 +1,52 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::net::IpAddr;
+use std::num::ParseIntError;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use egui::{ClippedPrimitive, Color32, ComboBox, Context, ScrollArea, Slider, TexturesDelta};
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+use egui::{
+    Align2, ClippedPrimitive, Color32, ComboBox, Context, FontId, Id, LayerId, Order, Pos2,
+    ProgressBar, Rect, ScrollArea, Slider, Stroke, TexturesDelta, Vec2,
+};
 use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use gilrs::Button;
 use pixels::{wgpu, PixelsContext};
+use winit::event::VirtualKeyCode;
 use winit::event_loop::{EventLoop, EventLoopProxy};
 use winit::window::Window;
 
-use crate::chip8::hardware::Generation;
-use crate::chip8::EmulatorEvents;
-use crate::display_bus::{AppEvents, DebugState};
+use crate::chip8::clock::DEFAULT_CPU_HZ;
+use crate::chip8::debug_map::map_op;
+use crate::chip8::hardware::MEMORY_SIZE;
+use crate::chip8::hardware::{Generation, Quirks};
+use crate::chip8::screen::{DrawMode, Palette, PalettePreset, Resolution, SCREEN_WIDTH};
+use crate::chip8::{
+    rom, EmulatorEvents, DEFAULT_MEMORY_WINDOW, DEFAULT_REWIND_DEPTH, RUN_TO_MAX_CYCLES,
+};
+use crate::display_bus::{
+    send_event, AppEvents, ConnectionStatus, DebugState, EmulatorStats, OpcodeCount,
+};
+use crate::io::gamepad::{self, ButtonMap, BUTTON_MAP};
+use crate::io::keymap::{self, KeyMap};
+use crate::io::KEY_MAP;
+use crate::paths;
 
-use super::debug_map::map_op;
-use super::emulator_view::EmulatorView;
-use super::{fetch_global_ip, EmulatorKind, HostIp};
+use super::asm_editor::AsmEditor;
+use super::disassembly_view::{DisassemblyAction, DisassemblyView};
+use super::emulator_view::{EmulatorView, CONNECT_TIMEOUT_SECS, DEFAULT_PORT, RECONNECT_ATTEMPTS};
+use super::memory_view::MemoryView;
+use super::playlist::Playlist;
+use super::recent_roms::RecentRoms;
+use super::settings::Settings;
+use super::{
+    fetch_global_ip, test_connection, EmulatorKind, HostIp, DUMP_MEMORY_KEY, NEXT_ROM_KEY,
+    PAUSE_KEY, PREVIOUS_ROM_KEY, SCREENSHOT_KEY, TURBO_KEY,
+};
 
 /// Manages all state required for rendering egui over `Pixels`.
 pub(crate) struct Framework {
@@ -70,6 +104,11 @@ impl Framework {
         }
     }
 
+    /// Returns true if egui is using the pointer, e.g. because it's hovering a window.
+    pub(crate) fn wants_pointer_input(&self) -> bool {
+        self.egui_ctx.wants_pointer_input()
+    }
+
     /// Handle input events from the window manager.
     pub(crate) fn handle_event(&mut self, event: &winit::event::WindowEvent) {
         let _ = self.egui_state.on_event(&self.egui_ctx, event);
@@ -88,13 +127,22 @@ impl Framework {
     }
 
     /// Prepare egui.
-    pub(crate) fn prepare(&mut self, window: &Window) {
+    ///
+    /// `clip_rect` is the area of the window (in physical pixels) the emulator display is drawn to.
+    pub(crate) fn prepare(&mut self, window: &Window, clip_rect: (u32, u32, u32, u32)) {
+        let (x, y, width, height) = clip_rect;
+        let pixels_per_point = self.screen_descriptor.pixels_per_point;
+        let display_rect = Rect::from_min_size(
+            Pos2::new(x as f32 / pixels_per_point, y as f32 / pixels_per_point),
+            Vec2::new(width as f32, height as f32) / pixels_per_point,
+        );
         // Run the egui frame and create all paint jobs to prepare for rendering.
         let raw_input = self.egui_state.take_egui_input(window);
         let output = self.egui_ctx.run(raw_input, |egui_ctx| {
             // Draw the application.
-            self.gui.ui(egui_ctx);
+            self.gui.ui(egui_ctx, display_rect);
         });
+        self.gui.save_changed_settings();
 
         self.textures.append(output.textures_delta);
         self.egui_state
@@ -150,83 +198,962 @@ impl Framework {
 }
 /// Example application state. A real application will need a lot more state than this.
 pub struct Gui {
-    pub color: Color32,
+    /// Colors of the display, either from the preset or picked by the user.
+    pub palette: Palette,
+    palette_preset: PalettePreset,
     /// Only show the egui window when true.
     window_open: bool,
+    /// Show the about window.
+    about_open: bool,
+    /// Show the key mapping window.
+    key_mapping_open: bool,
+    /// Show the assembler window.
+    assembler_open: bool,
+    /// Show the profiler window, the emulator counts the executed opcodes while it's open.
+    pub profiler_open: bool,
+    /// Latest opcode counts of the profiler and the instructions executed in total.
+    pub profile: Option<(Vec<OpcodeCount>, u64)>,
+    assembler: AsmEditor,
     pub event_bus: EventLoopProxy<AppEvents>,
     pub debugger: Option<Debugger>,
     start_debugger: bool,
+    /// Halt the cpu of a new emulator before it executes the first instruction.
+    start_paused: bool,
+    /// Maximum number of entries kept in the instruction and register history of the debugger.
+    debug_history_limit: usize,
+    /// Number of states the emulator keeps for stepping back.
+    rewind_depth: usize,
+    /// Number of bytes around pc and i the emulator reports to the memory and disassembly views.
+    memory_window: usize,
     generation: Generation,
+    quirks: Quirks,
     emulator_kind: EmulatorKind,
     file: Option<PathBuf>,
-    fps: u32,
+    /// Run the embedded demo instead of `file`.
+    use_builtin_program: bool,
+    /// Instructions the emulator executes per second.
+    cpu_hz: u32,
+    /// Seed of the random numbers as typed, random if empty.
+    seed: String,
+    /// Connection state of a client. `None` if no client was spawned yet.
+    pub connection_status: Option<ConnectionStatus>,
+    /// Why the last emulator couldn't be created, e.g. because the host wasn't reachable.
+    pub spawn_error: Option<String>,
+    /// Outcome of the last connection test.
+    pub connection_test: Option<String>,
+    /// Resolution the display currently shows.
+    pub resolution: Resolution,
+    /// Draw gridlines at each chip8 pixel boundary over the display.
+    show_grid: bool,
+    /// Label the gridlines with their pixel coordinates.
+    show_grid_labels: bool,
+    /// Show an indicator in the corner of the display while VF is set.
+    show_flag_indicator: bool,
+    /// Show the measured and target speed over the display.
+    show_speed: bool,
+    /// Frames rendered since `frames_counted_since`.
+    frames_counted: u32,
+    frames_counted_since: Instant,
+    /// Frames the app rendered during the last second.
+    rendered_fps: u32,
+    /// Silence the buzzer of the emulator.
+    muted: bool,
+    /// Whether the running emulator is paused.
+    paused: bool,
+    /// Pause the emulator while the window isn't focused.
+    pause_on_focus_loss: bool,
+    /// Set while the emulator is paused because the window lost focus, so it's resumed on focus.
+    paused_by_focus: bool,
+    /// Whether the emulator records or plays back a replay.
+    replay_active: bool,
+    /// Whether VF is currently set, as reported by the emulator.
+    pub flag_set: bool,
+    /// Set when the running program exited with 00FD.
+    pub program_exited: bool,
+    /// Error the emulator stopped with.
+    pub emulator_error: Option<String>,
+    /// Why the last rom dropped onto the window couldn't be loaded.
+    rom_error: Option<String>,
+    /// Latest counters of the running emulator.
+    pub stats: Option<EmulatorStats>,
+    /// Keyboard keys of the chip8 keys, loaded from the key config.
+    pub key_map: KeyMap,
+    /// Chip8 key that's mapped to the next pressed keyboard key.
+    rebinding: Option<usize>,
+    /// Gamepad buttons of the chip8 keys, loaded from the button config.
+    pub button_map: ButtonMap,
+    /// Chip8 key that's mapped to the next pressed gamepad button.
+    rebinding_button: Option<usize>,
+    /// Text briefly shown over the display, like the speed after scrolling, and when it was shown first.
+    overlay: Option<(String, Instant)>,
+    /// Roms of the folder opened from the file menu.
+    playlist: Option<Playlist>,
+    /// Roms opened in previous runs, shown in the file menu.
+    pub recent_roms: RecentRoms,
+    /// Scale of the display resolution the window should be resized to.
+    pub requested_scale: Option<u32>,
+    /// Snap the window size to multiples of the display resolution when resizing.
+    pub snap_to_integer_scale: bool,
+    /// Frames a pixel stays lit after it was turned off, to reduce flicker. 0 disables it.
+    pub persistence_frames: u8,
+    /// Fade turned off pixels out over the persistence frames instead of keeping them lit.
+    pub phosphor_fade: bool,
+    /// Save a screenshot of the display with the next frame.
+    pub screenshot_requested: bool,
+    /// Where the last screenshot was saved or why it failed.
+    pub screenshot_result: Option<String>,
+    /// Settings as they were saved last, to save them again once they change.
+    saved_settings: Settings,
 }
 #[derive(Default, Debug, PartialEq)]
 pub struct Debugger {
     pub current: DebugState,
-    pub op_hist: Vec<u16>,
+    pub op_hist: VecDeque<u16>,
+    /// Registers after each entry of `op_hist`.
+    reg_hist: VecDeque<[u8; 16]>,
+    /// Number of entries that were trimmed from the front of `op_hist`.
+    op_hist_offset: usize,
+    freeze_timers: bool,
+    halt_cpu: bool,
+    number_format: NumberFormat,
+    /// The most recent frames of the display with the resolution they were drawn in, newest last.
+    frame_history: VecDeque<(Resolution, Vec<u8>)>,
+    /// How many frames back from the newest one the frame viewer shows.
+    frames_back: usize,
+    show_register_plot: bool,
+    /// Draw sprites without erasing pixels. Only changes what's shown, collisions still use XOR.
+    or_draw_mode: bool,
+    /// Registers drawn in the register plot.
+    plotted_registers: [bool; 16],
+    show_memory: bool,
+    memory_view: MemoryView,
+    show_disassembly: bool,
+    disassembly_view: DisassemblyView,
+    /// Addresses the emulator stops at when running outside of the debugger.
+    breakpoints: Vec<u16>,
+    /// Hex address typed into the breakpoint field.
+    breakpoint_input: String,
+    /// Hex address typed into the jump field.
+    jump_input: String,
+    /// Address of the last run to an address that gave up before reaching it.
+    pub run_to_missed: Option<u16>,
+    /// Whether the emulator logs the executed instructions to a file.
+    tracing: bool,
+}
+pub const DEFAULT_DEBUG_HISTORY_LIMIT: usize = 10_000;
+/// Number of display frames kept for the frame viewer of the debugger.
+/// Independent of the debug history limit, since a frame takes far more memory than a history entry.
+const FRAME_HISTORY_LEN: usize = 120;
+/// Number of history entries included when copying the debugger state.
+const REPORTED_HISTORY: usize = 8;
+/// Window scales offered in the view menu.
+const WINDOW_SCALES: [u32; 4] = [4, 8, 12, 16];
+/// Range of instructions per second the emulator can be set to.
+const CPU_HZ_RANGE: RangeInclusive<u32> = 1..=5000;
+/// Range of frames a turned off pixel can stay lit.
+const PERSISTENCE_RANGE: RangeInclusive<u8> = 0..=10;
+/// How long the overlay text is shown over the display.
+const OVERLAY_DURATION: Duration = Duration::from_secs(1);
+/// Chip8 keys in the layout of the keypad.
+const KEYPAD: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+/// Keys of the app that can't be mapped to chip8 keys.
+const RESERVED_KEYS: [VirtualKeyCode; 5] = [
+    VirtualKeyCode::Escape,
+    DUMP_MEMORY_KEY,
+    SCREENSHOT_KEY,
+    NEXT_ROM_KEY,
+    PREVIOUS_ROM_KEY,
+];
+/// File extension of save-states.
+const SAVE_STATE_EXTENSION: &str = "c8state";
+/// Extension of replay files.
+const REPLAY_EXTENSION: &str = "c8replay";
+/// Controls how numbers are displayed in the debugger.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NumberFormat {
+    Hex,
+    Dec,
+    #[default]
+    Both,
+}
+impl NumberFormat {
+    fn format(self, v: u16) -> String {
+        match self {
+            NumberFormat::Hex => format!("{v:x}"),
+            NumberFormat::Dec => format!("{v}"),
+            NumberFormat::Both => format!("[{v}] ({v:x})"),
+        }
+    }
 }
 
 impl Gui {
     /// Create a `Gui`.
     fn new(event_bus: EventLoopProxy<AppEvents>) -> Self {
-        Self {
+        let settings = Settings::load();
+        let mut gui = Self {
             window_open: true,
-            color: Color32::LIGHT_GRAY,
+            about_open: false,
+            profiler_open: false,
+            profile: None,
+            key_mapping_open: false,
+            assembler_open: false,
+            assembler: AsmEditor::default(),
+            palette: Palette::default(),
+            palette_preset: PalettePreset::default(),
             event_bus,
             debugger: None,
             start_debugger: false,
+            start_paused: false,
+            debug_history_limit: DEFAULT_DEBUG_HISTORY_LIMIT,
+            rewind_depth: DEFAULT_REWIND_DEPTH,
+            memory_window: DEFAULT_MEMORY_WINDOW,
             generation: Generation::default(),
+            quirks: Quirks::default(),
             emulator_kind: EmulatorKind::Single,
             file: None,
-            fps: 60,
+            use_builtin_program: false,
+            cpu_hz: DEFAULT_CPU_HZ,
+            seed: String::new(),
+            connection_status: None,
+            spawn_error: None,
+            connection_test: None,
+            resolution: Resolution::default(),
+            show_grid: false,
+            show_grid_labels: false,
+            show_flag_indicator: false,
+            show_speed: false,
+            frames_counted: 0,
+            frames_counted_since: Instant::now(),
+            rendered_fps: 0,
+            muted: false,
+            paused: false,
+            pause_on_focus_loss: true,
+            paused_by_focus: false,
+            replay_active: false,
+            flag_set: false,
+            program_exited: false,
+            emulator_error: None,
+            rom_error: None,
+            stats: None,
+            key_map: KEY_MAP,
+            rebinding: None,
+            button_map: BUTTON_MAP,
+            rebinding_button: None,
+            overlay: None,
+            playlist: None,
+            recent_roms: RecentRoms::default(),
+            requested_scale: None,
+            snap_to_integer_scale: false,
+            persistence_frames: 0,
+            phosphor_fade: false,
+            screenshot_requested: false,
+            screenshot_result: None,
+            saved_settings: settings.clone(),
+        };
+        gui.apply_settings(settings);
+        gui
+    }
+    /// The settings kept between runs.
+    fn settings(&self) -> Settings {
+        Settings {
+            generation: self.generation,
+            quirks: self.quirks,
+            cpu_hz: self.cpu_hz,
+            emulator_kind: self.emulator_kind.clone(),
+            use_builtin_program: self.use_builtin_program,
+            start_debugger: self.start_debugger,
+            start_paused: self.start_paused,
+            debug_history_limit: self.debug_history_limit,
+            rewind_depth: self.rewind_depth,
+            memory_window: self.memory_window,
+            palette_preset: self.palette_preset,
+            palette: self.palette,
+            show_grid: self.show_grid,
+            show_grid_labels: self.show_grid_labels,
+            show_flag_indicator: self.show_flag_indicator,
+            show_speed: self.show_speed,
+            muted: self.muted,
+            pause_on_focus_loss: self.pause_on_focus_loss,
+            snap_to_integer_scale: self.snap_to_integer_scale,
+            persistence_frames: self.persistence_frames,
+            phosphor_fade: self.phosphor_fade,
         }
     }
-    pub fn update_debugger(&mut self, state: DebugState) {
-        if let Some(debugger) = &mut self.debugger {
-            debugger.op_hist.push(state.op);
-            debugger.current = state;
+    fn apply_settings(&mut self, settings: Settings) {
+        self.generation = settings.generation;
+        self.quirks = settings.quirks;
+        self.cpu_hz = settings
+            .cpu_hz
+            .clamp(*CPU_HZ_RANGE.start(), *CPU_HZ_RANGE.end());
+        self.emulator_kind = match settings.emulator_kind {
+            // the global ip may have changed since, so it's fetched again
+            EmulatorKind::Server { port, .. } => EmulatorKind::Server {
+                ip: HostIp::Empty,
+                port,
+            },
+            kind => kind,
+        };
+        self.use_builtin_program = settings.use_builtin_program;
+        self.start_debugger = settings.start_debugger;
+        self.start_paused = settings.start_paused;
+        self.debug_history_limit = settings.debug_history_limit;
+        self.rewind_depth = settings.rewind_depth;
+        self.memory_window = settings.memory_window.clamp(16, MEMORY_SIZE);
+        self.palette_preset = settings.palette_preset;
+        self.palette = settings
+            .palette_preset
+            .palette()
+            .unwrap_or(settings.palette);
+        self.show_grid = settings.show_grid;
+        self.show_grid_labels = settings.show_grid_labels;
+        self.show_flag_indicator = settings.show_flag_indicator;
+        self.show_speed = settings.show_speed;
+        self.muted = settings.muted;
+        self.pause_on_focus_loss = settings.pause_on_focus_loss;
+        self.snap_to_integer_scale = settings.snap_to_integer_scale;
+        self.persistence_frames = settings.persistence_frames.min(*PERSISTENCE_RANGE.end());
+        self.phosphor_fade = settings.phosphor_fade;
+    }
+    /// Saves the settings if they changed since they were saved last.
+    fn save_changed_settings(&mut self) {
+        let settings = self.settings();
+        if settings != self.saved_settings {
+            settings.save();
+            self.saved_settings = settings;
+        }
+    }
+    /// Changes the instructions per second by `delta` and briefly shows the new value over the display.
+    pub fn adjust_cpu_hz(&mut self, delta: i32) {
+        let cpu_hz = (self.cpu_hz as i32 + delta)
+            .clamp(*CPU_HZ_RANGE.start() as i32, *CPU_HZ_RANGE.end() as i32);
+        self.cpu_hz = cpu_hz as u32;
+        send_event(
+            &self.event_bus,
+            AppEvents::EmulatorEvent(EmulatorEvents::SetCpuHz(self.cpu_hz)),
+        );
+        self.show_overlay(format!("{} Hz", self.cpu_hz));
+    }
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.paused_by_focus = false;
+        send_event(
+            &self.event_bus,
+            AppEvents::EmulatorEvent(EmulatorEvents::Pause(self.paused)),
+        );
+        self.show_overlay(if self.paused { "paused" } else { "resumed" }.to_string());
+    }
+    /// Shows that the played back replay ended, which paused the emulator.
+    pub fn replay_finished(&mut self) {
+        self.replay_active = false;
+        self.paused = true;
+        self.paused_by_focus = false;
+        self.show_overlay("replay finished".to_string());
+    }
+    /// Pauses the emulator when the window loses focus and resumes it when focused again,
+    /// unless it was paused before.
+    pub fn focus_changed(&mut self, focused: bool) {
+        let toggle = if focused {
+            self.paused_by_focus
         } else {
-            let op = state.op;
-            self.debugger = Some(Debugger {
-                current: state,
-                op_hist: vec![op],
+            self.pause_on_focus_loss && !self.paused
+        };
+        if toggle {
+            self.toggle_pause();
+            self.paused_by_focus = !focused;
+        }
+    }
+    /// Tests the connection of `kind` in the background and reports the result to the app.
+    fn spawn_connection_test(kind: EmulatorKind, event_bus: EventLoopProxy<AppEvents>) {
+        thread::spawn(move || {
+            let result = test_connection(kind).map_err(|e| e.to_string());
+            send_event(&event_bus, AppEvents::ConnectionTested(result));
+        });
+    }
+    fn show_overlay(&mut self, text: String) {
+        self.overlay = Some((text, Instant::now()));
+    }
+    /// Loads the rom `delta` entries away in the playlist into the running emulator.
+    /// Roms that can't be loaded are skipped.
+    pub fn switch_rom(&mut self, delta: isize) {
+        let Some(playlist) = &mut self.playlist else {
+            return;
+        };
+        for _ in 0..playlist.len() {
+            playlist.step(delta);
+            let Some(path) = playlist.current() else {
+                return;
+            };
+            match rom::read_rom(path) {
+                Ok(program) => {
+                    let path = path.to_path_buf();
+                    send_event(
+                        &self.event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::LoadRom(program)),
+                    );
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    self.show_overlay(name.into_owned());
+                    self.recent_roms.push(&path);
+                    self.file = Some(path);
+                    self.program_exited = false;
+                    self.emulator_error = None;
+                    return;
+                }
+                Err(e) => eprintln!("skipping rom {path:?} with {e}"),
+            }
+            // step in the same direction when skipping from the current rom
+            if delta == 0 {
+                playlist.step(1);
+            }
+        }
+    }
+    /// Runs the rom dropped onto the window in a new emulator.
+    /// Returns false and shows the error if the rom can't be loaded.
+    pub fn open_dropped_rom(&mut self, path: PathBuf) -> bool {
+        if matches!(self.emulator_kind, EmulatorKind::Client { .. }) {
+            self.rom_error = Some("clients run the rom of their host".to_string());
+            return false;
+        }
+        if let Err(e) = rom::check_rom_file(&path).and_then(|()| rom::read_rom(&path)) {
+            self.rom_error = Some(format!("couldn't load {}: {e}", path.display()));
+            return false;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.show_overlay(name.into_owned());
+        self.recent_roms.push(&path);
+        self.file = Some(path);
+        self.use_builtin_program = false;
+        self.create_emulator();
+        true
+    }
+    /// Draw mode of the display, OR only while the debugger asks for it.
+    pub fn draw_mode(&self) -> DrawMode {
+        match &self.debugger {
+            Some(debugger) if debugger.or_draw_mode => DrawMode::Or,
+            _ => DrawMode::Xor,
+        }
+    }
+    pub fn update_debugger(&mut self, state: DebugState) {
+        let debugger = self.debugger.get_or_insert_with(Debugger::default);
+        debugger.op_hist.push_back(state.op);
+        debugger.reg_hist.push_back(state.reg);
+        debugger.current = state;
+        debugger.trim_history(self.debug_history_limit);
+    }
+
+    /// Draws faint gridlines at each logical pixel boundary of the display.
+    fn draw_grid(&self, ctx: &Context, display_rect: Rect) {
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("pixel_grid")));
+        let (width, height) = (self.resolution.width(), self.resolution.height());
+        let cell = Vec2::new(
+            display_rect.width() / width as f32,
+            display_rect.height() / height as f32,
+        );
+        let stroke = Stroke::new(1., Color32::from_white_alpha(24));
+        let label_color = Color32::from_white_alpha(120);
+        let font = FontId::monospace(8.);
+        for column in 0..=width {
+            let x = display_rect.left() + column as f32 * cell.x;
+            painter.vline(x, display_rect.y_range(), stroke);
+            if self.show_grid_labels && column % 8 == 0 && column < width {
+                let pos = Pos2::new(x + 1., display_rect.top() + 1.);
+                painter.text(pos, Align2::LEFT_TOP, column, font.clone(), label_color);
+            }
+        }
+        for row in 0..=height {
+            let y = display_rect.top() + row as f32 * cell.y;
+            painter.hline(display_rect.x_range(), y, stroke);
+            if self.show_grid_labels && row % 8 == 0 && row != 0 && row < height {
+                let pos = Pos2::new(display_rect.left() + 1., y + 1.);
+                painter.text(pos, Align2::LEFT_TOP, row, font.clone(), label_color);
+            }
+        }
+    }
+
+    /// Draws a small dot in the top right corner of the display.
+    fn draw_flag_indicator(&self, ctx: &Context, display_rect: Rect) {
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("flag_indicator")));
+        let radius = 4.;
+        let center = display_rect.right_top() + Vec2::new(-2. * radius, 2. * radius);
+        painter.circle_filled(center, radius, Color32::RED);
+    }
+    /// Counts the rendered frames, updating `rendered_fps` once a second.
+    fn count_frame(&mut self) {
+        self.frames_counted += 1;
+        let elapsed = self.frames_counted_since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.rendered_fps = (self.frames_counted as f32 / elapsed.as_secs_f32()).round() as u32;
+            self.frames_counted = 0;
+            self.frames_counted_since = Instant::now();
+        }
+    }
+    /// Draws the achieved and target instructions per second and the rendered fps in the corner of the display.
+    fn draw_speed(&self, ctx: &Context, display_rect: Rect) {
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("speed")));
+        let text = match &self.stats {
+            Some(stats) => format!(
+                "{} / {} ips\n{} fps",
+                stats.instructions_per_second, stats.target_hz, self.rendered_fps
+            ),
+            None => format!("{} fps", self.rendered_fps),
+        };
+        painter.text(
+            display_rect.left_top() + Vec2::new(4., 4.),
+            Align2::LEFT_TOP,
+            text,
+            FontId::monospace(12.),
+            Color32::WHITE,
+        );
+    }
+
+    /// Explains why no emulator can be created with the current settings.
+    /// `None` if the emulator can be created.
+    fn spawn_hint(&self) -> Option<&'static str> {
+        if self.seed().is_err() {
+            return Some("the seed has to be a number or empty");
+        }
+        match &self.emulator_kind {
+            EmulatorKind::Client { port: 0, .. } | EmulatorKind::Server { port: 0, .. } => {
+                Some("the port has to be between 1 and 65535")
+            }
+            EmulatorKind::Client { host_ip, .. } => IpAddr::from_str(host_ip)
+                .is_err()
+                .then_some("enter a valid host ip addr"),
+            EmulatorKind::Single | EmulatorKind::Server { .. } => (self.file.is_none()
+                && !self.use_builtin_program)
+                .then_some("select a program or use the built-in demo"),
+        }
+    }
+
+    /// Seed typed for the random numbers, `None` if it's empty.
+    fn seed(&self) -> Result<Option<u64>, ParseIntError> {
+        let seed = self.seed.trim();
+        if seed.is_empty() {
+            return Ok(None);
+        }
+        seed.parse().map(Some)
+    }
+    /// Returns true while waiting for a keyboard key to map a chip8 key to.
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+    /// Maps the chip8 key chosen in the key mapping window to `keyboard_key` and saves the key config.
+    /// Keys used by the app itself can't be mapped.
+    pub fn rebind_key(&mut self, keyboard_key: VirtualKeyCode) {
+        if RESERVED_KEYS.contains(&keyboard_key) {
+            return;
+        }
+        let Some(key) = self.rebinding.take() else {
+            return;
+        };
+        keymap::rebind(&mut self.key_map, key, keyboard_key);
+        self.save_key_map();
+    }
+    /// Returns true while waiting for a gamepad button to map a chip8 key to.
+    pub fn is_rebinding_button(&self) -> bool {
+        self.rebinding_button.is_some()
+    }
+    /// Maps the chip8 key chosen in the key mapping window to `button` and saves the button config.
+    pub fn rebind_button(&mut self, button: Button) {
+        let Some(key) = self.rebinding_button.take() else {
+            return;
+        };
+        gamepad::rebind(&mut self.button_map, key, button);
+        self.save_button_map();
+    }
+    fn save_button_map(&self) {
+        if let Some(path) = paths::button_config() {
+            if let Err(e) = gamepad::save(&path, &self.button_map) {
+                eprintln!("couldn't write button config {path:?} with {e}");
+            }
+        }
+    }
+    fn save_key_map(&self) {
+        if let Some(path) = paths::key_config() {
+            if let Err(e) = keymap::save(&path, &self.key_map) {
+                eprintln!("couldn't write key config {path:?} with {e}");
+            }
+        }
+    }
+    fn key_mapping_ui(&mut self, ctx: &Context) {
+        let mut open = self.key_mapping_open;
+        egui::Window::new("Key mapping")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                egui::Grid::new("key_mapping").show(ui, |ui| {
+                    for row in KEYPAD {
+                        for key in row {
+                            let text = if self.rebinding == Some(key) {
+                                format!("{key:X} = ...")
+                            } else {
+                                format!("{key:X} = {:?}", self.key_map[key])
+                            };
+                            if ui.button(text).clicked() {
+                                self.rebinding = Some(key);
+                                self.rebinding_button = None;
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(key) = self.rebinding {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("press a key for {key:X}"));
+                        if ui.button("cancel").clicked() {
+                            self.rebinding = None;
+                        }
+                    });
+                }
+                if ui.button("reset to default").clicked() {
+                    self.key_map = KEY_MAP;
+                    self.save_key_map();
+                }
+                ui.separator();
+                ui.label("Gamepad");
+                egui::Grid::new("button_mapping").show(ui, |ui| {
+                    for row in KEYPAD {
+                        for key in row {
+                            let text =
+                                match (self.rebinding_button == Some(key), self.button_map[key]) {
+                                    (true, _) => format!("{key:X} = ..."),
+                                    (false, Some(button)) => format!("{key:X} = {button:?}"),
+                                    (false, None) => format!("{key:X} = -"),
+                                };
+                            if ui.button(text).clicked() {
+                                self.rebinding_button = Some(key);
+                                self.rebinding = None;
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(key) = self.rebinding_button {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("press a gamepad button for {key:X}"));
+                        if ui.button("unbind").clicked() {
+                            self.button_map[key] = None;
+                            self.rebinding_button = None;
+                            self.save_button_map();
+                        }
+                        if ui.button("cancel").clicked() {
+                            self.rebinding_button = None;
+                        }
+                    });
+                }
+                if ui.button("reset gamepad to default").clicked() {
+                    self.button_map = BUTTON_MAP;
+                    self.save_button_map();
+                }
+            });
+        self.key_mapping_open = open;
+        if !open {
+            self.rebinding = None;
+            self.rebinding_button = None;
+        }
+    }
+    fn profiler_ui(&mut self, ctx: &Context) {
+        let mut reset = false;
+        let mut open = self.profiler_open;
+        egui::Window::new("Profiler")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some((counts, total)) = &self.profile else {
+                    ui.label("the counts are shown after the next second");
+                    return;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(format!("instructions: {total}"));
+                    reset = ui.button("Reset").clicked();
+                });
+                let most = counts.first().map_or(1, |count| count.count.max(1));
+                ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                    for count in counts {
+                        let share = count.count as f64 / (*total).max(1) as f64 * 100.;
+                        let text = format!(
+                            "{}  {} ({share:.1}%)  e.g. {}",
+                            count.pattern,
+                            count.count,
+                            map_op(count.example)
+                        );
+                        ui.add(ProgressBar::new(count.count as f32 / most as f32).text(text));
+                    }
+                });
             });
+        if reset {
+            self.profile = None;
+            send_event(
+                &self.event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::ResetProfile),
+            );
         }
+        if self.profiler_open && !open {
+            self.set_profiling(false);
+        }
+    }
+    /// Opens or closes the profiler, telling the emulator to count the opcodes while it's open.
+    fn set_profiling(&mut self, profiling: bool) {
+        self.profiler_open = profiling;
+        self.profile = None;
+        send_event(
+            &self.event_bus,
+            AppEvents::EmulatorEvent(EmulatorEvents::SetProfiling(profiling)),
+        );
+    }
+    /// Shows the version, the keybindings and the active configuration.
+    fn about_ui(&mut self, ctx: &Context) {
+        egui::Window::new("About")
+            .open(&mut self.about_open)
+            .show(ctx, |ui| {
+                ui.heading(format!("chip8 v{}", env!("CARGO_PKG_VERSION")));
+                ui.separator();
+                ui.label("Controls");
+                egui::Grid::new("about_keys").striped(true).show(ui, |ui| {
+                    for row in KEYPAD {
+                        for key in row {
+                            ui.label(format!("{key:X} = {:?}", self.key_map[key]));
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.label("Esc = quit");
+                ui.label(format!("{DUMP_MEMORY_KEY:?} = dump memory"));
+                ui.label(format!("{SCREENSHOT_KEY:?} = screenshot"));
+                ui.label(format!(
+                    "{NEXT_ROM_KEY:?} / {PREVIOUS_ROM_KEY:?} = next / previous rom of the rom folder"
+                ));
+                ui.label(format!("{PAUSE_KEY:?} = pause / resume"));
+                ui.label(format!("hold {TURBO_KEY:?} = fast-forward"));
+                ui.label("mouse wheel over the display = change the cpu speed");
+                ui.separator();
+                ui.label(format!("Architecture: {:?}", self.generation));
+                ui.label(format!("Quirks: {:#?}", self.quirks));
+                ui.label(format!("cpu: {} Hz", self.cpu_hz));
+            });
     }
 
     /// Create the UI using egui.
-    fn ui(&mut self, ctx: &Context) {
-        if let Some(debugger) = &self.debugger {
-            debugger.ui(ctx, &self.event_bus);
+    fn ui(&mut self, ctx: &Context, display_rect: Rect) {
+        self.count_frame();
+        if self.show_speed {
+            self.draw_speed(ctx, display_rect);
+        }
+        if self.show_grid {
+            self.draw_grid(ctx, display_rect);
+        }
+        if self.show_flag_indicator && self.flag_set {
+            self.draw_flag_indicator(ctx, display_rect);
+        }
+        if let Some((text, since)) = &self.overlay {
+            if since.elapsed() < OVERLAY_DURATION {
+                let painter =
+                    ctx.layer_painter(LayerId::new(Order::Background, Id::new("overlay")));
+                painter.text(
+                    display_rect.center_top() + Vec2::new(0., 8.),
+                    Align2::CENTER_TOP,
+                    text,
+                    FontId::proportional(16.),
+                    Color32::WHITE,
+                );
+            } else {
+                self.overlay = None;
+            }
+        }
+        if let Some(debugger) = &mut self.debugger {
+            let stopped = self
+                .emulator_error
+                .as_deref()
+                .or(self.program_exited.then_some("program exited"));
+            debugger.ui(ctx, &self.event_bus, stopped);
         }
         egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
-                    if ui.button("About...").clicked() {
+                    if ui.button("Emulator...").clicked() {
                         self.window_open = true;
                         ui.close_menu();
                     }
-                })
+                    if ui.button("Open rom folder...").clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            match Playlist::from_dir(&dir) {
+                                Ok(playlist) => {
+                                    self.playlist = Some(playlist);
+                                    self.switch_rom(0);
+                                }
+                                Err(e) => eprintln!("couldn't read rom folder {dir:?} with {e}"),
+                            }
+                        }
+                        ui.close_menu();
+                    }
+                    let mut open_recent = None;
+                    ui.add_enabled_ui(!self.recent_roms.roms().is_empty(), |ui| {
+                        ui.menu_button("Open recent", |ui| {
+                            for rom in self.recent_roms.roms() {
+                                let name = rom.file_name().unwrap_or_default().to_string_lossy();
+                                if ui
+                                    .button(name)
+                                    .on_hover_text(rom.display().to_string())
+                                    .clicked()
+                                {
+                                    open_recent = Some(rom.clone());
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    if let Some(rom) = open_recent {
+                        self.recent_roms.push(&rom);
+                        self.file = Some(rom);
+                        self.use_builtin_program = false;
+                        self.create_emulator();
+                    }
+                    if ui.button("Assembler...").clicked() {
+                        self.assembler_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Key mapping...").clicked() {
+                        self.key_mapping_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("About...").clicked() {
+                        self.about_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Open data folder").clicked() {
+                        match paths::data_dir() {
+                            Some(dir) => {
+                                if let Err(e) = paths::open_dir(&dir) {
+                                    eprintln!("couldn't open data folder {dir:?} with {e}");
+                                }
+                            }
+                            None => eprintln!("couldn't determine the data folder"),
+                        }
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    for scale in WINDOW_SCALES {
+                        if ui.button(format!("Fit to {scale}x")).clicked() {
+                            self.requested_scale = Some(scale);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.checkbox(&mut self.snap_to_integer_scale, "Snap to integer scale");
+                    let mut profiling = self.profiler_open;
+                    if ui.checkbox(&mut profiling, "Opcode profiler").changed() {
+                        self.set_profiling(profiling);
+                    }
+                });
+                if let Some(error) = &self.rom_error {
+                    ui.colored_label(Color32::RED, error);
+                    if ui.small_button("x").clicked() {
+                        self.rom_error = None;
+                    }
+                }
             });
         });
+        self.about_ui(ctx);
+        self.profiler_ui(ctx);
+        self.key_mapping_ui(ctx);
+        self.assembler
+            .ui(ctx, &mut self.assembler_open, &self.event_bus);
+        let spawn_hint = self.spawn_hint();
+        let mut toggle_pause = false;
+        let mut create_emulator = false;
         egui::Window::new("Chip8")
             .open(&mut self.window_open)
             .show(ctx, |ui| {
                 ComboBox::from_label("Architecture")
                     .selected_text(format!("{:?}", self.generation))
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut self.generation,
-                            Generation::Super,
-                            format!("{:?}", Generation::Super),
+                        let super_changed = ui
+                            .selectable_value(
+                                &mut self.generation,
+                                Generation::Super,
+                                format!("{:?}", Generation::Super),
+                            )
+                            .changed();
+                        let cosmac_changed = ui
+                            .selectable_value(
+                                &mut self.generation,
+                                Generation::Cosmac,
+                                format!("{:?}", Generation::Cosmac),
+                            )
+                            .changed();
+                        if super_changed || cosmac_changed {
+                            self.quirks = Quirks::for_generation(self.generation);
+                        }
+                    });
+                ui.collapsing("Quirks", |ui| {
+                    if ui
+                        .checkbox(&mut self.quirks.shift_uses_vy, "shift uses VY (8XY6/8XYE)")
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetShiftQuirk(
+                                self.quirks.shift_uses_vy,
+                            )),
                         );
-                        ui.selectable_value(
-                            &mut self.generation,
-                            Generation::Cosmac,
-                            format!("{:?}", Generation::Cosmac),
+                    }
+                    if ui
+                        .checkbox(&mut self.quirks.jump_uses_vx, "jump uses VX (BXNN)")
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetJumpQuirk(
+                                self.quirks.jump_uses_vx,
+                            )),
                         );
-                    });
+                    }
+                    if ui
+                        .checkbox(&mut self.quirks.clip_sprites, "clip sprites at the edges")
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetClipQuirk(
+                                self.quirks.clip_sprites,
+                            )),
+                        );
+                    }
+                    if ui
+                        .checkbox(
+                            &mut self.quirks.load_store_increments_i,
+                            "load/store increments i (FX55/FX65)",
+                        )
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetLoadStoreQuirk(
+                                self.quirks.load_store_increments_i,
+                            )),
+                        );
+                    }
+                    if ui
+                        .checkbox(
+                            &mut self.quirks.display_wait,
+                            "draws wait for the display (DXYN)",
+                        )
+                        .on_hover_text(
+                            "limits draws to one per frame, some roms run too slowly with it",
+                        )
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetDisplayWaitQuirk(
+                                self.quirks.display_wait,
+                            )),
+                        );
+                    }
+                });
+                let mut start_connection_test = false;
                 ComboBox::from_label("Emulator kind")
                     .selected_text(format!("{}", self.emulator_kind))
                     .show_ui(ui, |ui| {
@@ -237,24 +1164,55 @@ impl Gui {
                         );
                         ui.selectable_value(
                             &mut self.emulator_kind,
-                            EmulatorKind::Server { ip: HostIp::Empty },
+                            EmulatorKind::Server {
+                                ip: HostIp::Empty,
+                                port: DEFAULT_PORT,
+                            },
                             "Server",
                         );
                         ui.selectable_value(
                             &mut self.emulator_kind,
                             EmulatorKind::Client {
                                 host_ip: String::default(),
+                                port: DEFAULT_PORT,
+                                connect_timeout_secs: CONNECT_TIMEOUT_SECS,
+                                reconnect_attempts: RECONNECT_ATTEMPTS,
                             },
                             "Client",
                         );
                     });
-                if let EmulatorKind::Client { host_ip } = &mut self.emulator_kind {
+                if let EmulatorKind::Client {
+                    host_ip,
+                    port,
+                    connect_timeout_secs,
+                    reconnect_attempts,
+                } = &mut self.emulator_kind
+                {
                     ui.horizontal(|ui| {
                         ui.text_edit_singleline(host_ip);
                         ui.label("host ip addr");
                     });
+                    port_ui(ui, port);
+                    ui.add(Slider::new(connect_timeout_secs, 1..=30).text("connect timeout (s)"));
+                    ui.add(Slider::new(reconnect_attempts, 0..=20).text("reconnect attempts"));
+                    if let Some(error) = &self.spawn_error {
+                        ui.colored_label(Color32::RED, format!("couldn't connect: {error}"));
+                    }
+                    start_connection_test = ui.button("test connection").clicked();
+                    match self.connection_status {
+                        Some(ConnectionStatus::Connected) => {
+                            ui.label("connected");
+                        }
+                        Some(ConnectionStatus::Reconnecting { attempt }) => {
+                            ui.label(format!("reconnecting... ({attempt}/{reconnect_attempts})"));
+                        }
+                        Some(ConnectionStatus::Disconnected) => {
+                            ui.label("disconnected");
+                        }
+                        Some(ConnectionStatus::Hosting { .. }) | None => {}
+                    }
                 }
-                if let EmulatorKind::Server { ip } = &mut self.emulator_kind {
+                if let EmulatorKind::Server { ip, port } = &mut self.emulator_kind {
                     if *ip == HostIp::Empty {
                         match fetch_global_ip() {
                             Some(fetched) => *ip = HostIp::Ip(fetched),
@@ -272,8 +1230,39 @@ impl Gui {
                         }
                         ui.label("host ip addr");
                     });
+                    port_ui(ui, port);
+                    start_connection_test = ui.button("test connection").clicked();
+                    match self.connection_status {
+                        Some(ConnectionStatus::Hosting { clients }) => {
+                            ui.horizontal(|ui| {
+                                match clients {
+                                    0 => ui.label("waiting for clients..."),
+                                    1 => ui.label("1 client connected"),
+                                    _ => ui.label(format!("{clients} clients connected")),
+                                };
+                                if ui.button("Stop hosting").clicked() {
+                                    send_event(&self.event_bus, AppEvents::CancelHosting);
+                                }
+                            });
+                        }
+                        Some(ConnectionStatus::Disconnected) => {
+                            ui.label("stopped accepting clients");
+                        }
+                        Some(ConnectionStatus::Connected)
+                        | Some(ConnectionStatus::Reconnecting { .. })
+                        | None => {}
+                    }
+                }
+                if start_connection_test {
+                    self.connection_test = Some("testing connection...".to_string());
+                    Self::spawn_connection_test(self.emulator_kind.clone(), self.event_bus.clone());
                 }
-                if !matches!(self.emulator_kind, EmulatorKind::Client { host_ip: _ }) {
+                if !matches!(self.emulator_kind, EmulatorKind::Single) {
+                    if let Some(result) = &self.connection_test {
+                        ui.label(result);
+                    }
+                }
+                if !matches!(self.emulator_kind, EmulatorKind::Client { .. }) {
                     let file_name = self
                         .file
                         .as_ref()
@@ -283,101 +1272,815 @@ impl Gui {
                                 .unwrap_or_default()
                         })
                         .unwrap_or_default();
-                    if ui.button(format!("program [{file_name:?}]")).clicked() {
+                    let mut pick_file = false;
+                    ui.horizontal(|ui| {
+                        pick_file = ui.button(format!("program [{file_name:?}]")).clicked();
+                        ui.checkbox(&mut self.use_builtin_program, "built-in demo");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("rng seed");
+                        ui.add(egui::TextEdit::singleline(&mut self.seed).desired_width(120.))
+                            .on_hover_text("same seeds give the same random numbers, leave empty for random ones");
+                    });
+                    if pick_file {
                         self.file = rfd::FileDialog::new().pick_file();
+                        if let Some(file) = &self.file {
+                            self.recent_roms.push(file);
+                        }
                     }
                 }
-                if ui.checkbox(&mut self.start_debugger, "debug").clicked() {
+                let debug_toggled = ui.horizontal(|ui| {
+                    let toggled = ui.checkbox(&mut self.start_debugger, "debug").clicked();
+                    // resuming happens through the halt toggle of the debugger window
+                    ui.add_enabled(
+                        self.start_debugger,
+                        egui::Checkbox::new(&mut self.start_paused, "start paused"),
+                    );
+                    toggled
+                });
+                if debug_toggled.inner {
                     if self.start_debugger {
                         self.debugger = Some(Debugger::default());
                     } else {
                         self.debugger = None;
+                        // the toggles live in the debugger window, so they can't stay active without it
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::FreezeTimers(false)),
+                        );
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::HaltCpu(false)),
+                        );
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::ReportMemory(false)),
+                        );
+                    }
+                    send_event(
+                        &self.event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::SetDebug(self.start_debugger)),
+                    );
+                }
+                if let Some(debugger) = &mut self.debugger {
+                    ui.horizontal(|ui| {
+                        let slider = Slider::new(&mut self.debug_history_limit, 100..=1_000_000)
+                            .logarithmic(true)
+                            .text("debug history");
+                        if ui.add(slider).changed() {
+                            debugger.trim_history(self.debug_history_limit);
+                        }
+                        ui.label(format!("~{} KiB", debugger.history_memory() / 1024));
+                        ui.label(format!(
+                            "frames: {} KiB",
+                            debugger.frame_history_memory() / 1024
+                        ))
+                        .on_hover_text(format!(
+                            "the last {FRAME_HISTORY_LEN} frames are kept regardless of the limit"
+                        ));
+                    });
+                    ui.horizontal(|ui| {
+                        let slider =
+                            Slider::new(&mut self.rewind_depth, 0..=10_000).text("rewind depth");
+                        if ui.add(slider).changed() {
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::SetRewindDepth(
+                                    self.rewind_depth,
+                                )),
+                            );
+                        }
+                        // every state holds a copy of the memory
+                        ui.label(format!("~{} KiB", self.rewind_depth * MEMORY_SIZE / 1024));
+                    });
+                    let slider = Slider::new(&mut self.memory_window, 16..=MEMORY_SIZE)
+                        .step_by(16.)
+                        .text("memory window");
+                    if ui
+                        .add(slider)
+                        .on_hover_text("bytes around pc and i shown in the memory and disassembly")
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::SetMemoryWindow(
+                                self.memory_window,
+                            )),
+                        );
                     }
-                    self.event_bus
-                        .send_event(AppEvents::EmulatorEvent(EmulatorEvents::SetDebug(
-                            self.start_debugger,
-                        )))
-                        .unwrap();
                 }
 
                 ui.separator();
-                if ui.color_edit_button_srgba(&mut self.color).changed() {
-                    self.event_bus
-                        .send_event(AppEvents::EmulatorEvent(EmulatorEvents::ChangeColor(
-                            self.color,
-                        )))
-                        .unwrap();
+                palette_ui(ui, &mut self.palette_preset, &mut self.palette);
+                if ui
+                    .add(
+                        Slider::new(&mut self.cpu_hz, CPU_HZ_RANGE)
+                            .logarithmic(true)
+                            .text("instructions per second"),
+                    )
+                    .changed()
+                {
+                    send_event(
+                        &self.event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::SetCpuHz(self.cpu_hz)),
+                    );
                 }
+                ui.horizontal(|ui| {
+                    ui.add(
+                        Slider::new(&mut self.persistence_frames, PERSISTENCE_RANGE)
+                            .text("anti-flicker frames"),
+                    );
+                    ui.add_enabled(
+                        self.persistence_frames > 0,
+                        egui::Checkbox::new(&mut self.phosphor_fade, "phosphor fade"),
+                    )
+                    .on_hover_text("turned off pixels fade out over the frames like on a CRT");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_grid, "pixel grid");
+                    ui.add_enabled(
+                        self.show_grid,
+                        egui::Checkbox::new(&mut self.show_grid_labels, "coordinates"),
+                    );
+                });
+                ui.checkbox(&mut self.show_speed, "speed overlay")
+                    .on_hover_text(
+                    "instructions per second against the set cpu speed, and rendered frames per second",
+                );
                 if ui
-                    .add(Slider::new(&mut self.fps, 1..=100).text("fps"))
+                    .checkbox(&mut self.show_flag_indicator, "VF indicator")
                     .changed()
                 {
-                    self.event_bus
-                        .send_event(AppEvents::EmulatorEvent(EmulatorEvents::FpsChange(
-                            self.fps,
-                        )))
-                        .unwrap();
+                    self.flag_set = false;
+                    send_event(
+                        &self.event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::ReportFlag(
+                            self.show_flag_indicator,
+                        )),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    let pause_text = if self.paused { "Resume" } else { "Pause" };
+                    toggle_pause = ui.button(pause_text).clicked();
+                    if ui.button("Reset").clicked() {
+                        self.program_exited = false;
+                        self.emulator_error = None;
+                        if let Some(debugger) = &mut self.debugger {
+                            debugger.clear_history();
+                        }
+                        send_event(
+                            &self.event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::Reset),
+                        );
+                    }
+                    if ui.button("Save state").clicked() {
+                        if let Some(path) = save_state_dialog().save_file() {
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::SaveState(path)),
+                            );
+                        }
+                    }
+                    if ui.button("Load state").clicked() {
+                        if let Some(path) = save_state_dialog().pick_file() {
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::LoadState(path)),
+                            );
+                        }
+                    }
+                    if ui.button("Screenshot").clicked() {
+                        self.screenshot_requested = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if self.replay_active {
+                        if ui.button("Stop replay").clicked() {
+                            self.replay_active = false;
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::StopReplay),
+                            );
+                        }
+                        return;
+                    }
+                    if ui
+                        .button("Record replay")
+                        .on_hover_text("restart the program and record the pressed keys")
+                        .clicked()
+                    {
+                        if let Some(path) = replay_dialog().save_file() {
+                            self.replay_active = true;
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::RecordReplay(path)),
+                            );
+                        }
+                    }
+                    if ui.button("Play replay").clicked() {
+                        if let Some(path) = replay_dialog().pick_file() {
+                            self.replay_active = true;
+                            send_event(
+                                &self.event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::PlayReplay(path)),
+                            );
+                        }
+                    }
+                });
+                if let Some(result) = &self.screenshot_result {
+                    ui.label(result);
+                }
+                if ui.checkbox(&mut self.muted, "mute").changed() {
+                    send_event(
+                        &self.event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::SetMuted(self.muted)),
+                    );
                 }
+                ui.checkbox(&mut self.pause_on_focus_loss, "pause without focus");
                 ui.separator();
-                if ui.button("Create Emulator").clicked() {
-                    self.event_bus
-                        .send_event(AppEvents::SpawnEmulator {
-                            kind: self.emulator_kind.clone(),
-                            generation: self.generation,
-                            debugger: self.start_debugger,
-                            path: self.file.clone(),
-                            fps: self.fps,
-                        })
-                        .expect("couldn't send `SpawnEmulator` event to main app");
+                if let Some(stats) = &self.stats {
+                    ui.label(format!(
+                        "instructions: {}  uptime: {}s  speed: {} / {} ips",
+                        stats.instructions,
+                        stats.uptime.as_secs(),
+                        stats.instructions_per_second,
+                        stats.target_hz
+                    ));
+                }
+                if self.program_exited {
+                    ui.label("program exited");
+                }
+                if let Some(error) = &self.emulator_error {
+                    ui.colored_label(Color32::RED, error);
+                }
+                // the client section shows why it couldn't connect
+                if !matches!(self.emulator_kind, EmulatorKind::Client { .. }) {
+                    if let Some(error) = &self.spawn_error {
+                        ui.colored_label(
+                            Color32::RED,
+                            format!("couldn't create emulator: {error}"),
+                        );
+                    }
+                }
+                if let Some(hint) = spawn_hint {
+                    ui.label(hint);
+                }
+                if ui
+                    .add_enabled(spawn_hint.is_none(), egui::Button::new("Create Emulator"))
+                    .clicked()
+                {
+                    create_emulator = true;
                 }
             });
+        if toggle_pause {
+            self.toggle_pause();
+        }
+        if create_emulator {
+            self.create_emulator();
+        }
+    }
+    /// Takes over the settings given on the command line.
+    pub fn apply_args(
+        &mut self,
+        rom: Option<PathBuf>,
+        generation: Option<Generation>,
+        cpu_hz: Option<u32>,
+    ) {
+        if let Some(rom) = rom {
+            self.recent_roms.push(&rom);
+            self.file = Some(rom);
+        }
+        if let Some(generation) = generation {
+            self.generation = generation;
+            self.quirks = Quirks::for_generation(generation);
+        }
+        if let Some(cpu_hz) = cpu_hz {
+            self.cpu_hz = cpu_hz.clamp(*CPU_HZ_RANGE.start(), *CPU_HZ_RANGE.end());
+        }
+    }
+    /// Spawns a new emulator with the current settings, replacing the running one.
+    pub fn create_emulator(&mut self) {
+        self.connection_status = None;
+        self.spawn_error = None;
+        self.rom_error = None;
+        self.program_exited = false;
+        self.emulator_error = None;
+        self.stats = None;
+        self.paused = false;
+        self.paused_by_focus = false;
+        self.replay_active = false;
+        // a new emulator starts with running timers and cpu unless it starts paused
+        let start_paused = self.start_debugger && self.start_paused;
+        if let Some(debugger) = &mut self.debugger {
+            debugger.freeze_timers = false;
+            debugger.halt_cpu = start_paused;
+            debugger.tracing = false;
+        }
+        send_event(
+            &self.event_bus,
+            AppEvents::SpawnEmulator {
+                kind: self.emulator_kind.clone(),
+                generation: self.generation,
+                quirks: self.quirks,
+                debugger: self.start_debugger,
+                start_paused,
+                path: self.file.clone().filter(|_| !self.use_builtin_program),
+                cpu_hz: self.cpu_hz,
+                seed: self.seed().ok().flatten(),
+            },
+        );
+        if self.show_flag_indicator {
+            self.flag_set = false;
+            send_event(
+                &self.event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::ReportFlag(true)),
+            );
+        }
+        if self.muted {
+            send_event(
+                &self.event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::SetMuted(true)),
+            );
+        }
+        if self.profiler_open {
+            self.set_profiling(true);
+        }
+        if self.rewind_depth != DEFAULT_REWIND_DEPTH {
+            send_event(
+                &self.event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::SetRewindDepth(self.rewind_depth)),
+            );
+        }
+        if self.memory_window != DEFAULT_MEMORY_WINDOW {
+            send_event(
+                &self.event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::SetMemoryWindow(self.memory_window)),
+            );
+        }
+        if let Some(debugger) = &self.debugger {
+            if !debugger.breakpoints.is_empty() {
+                send_event(
+                    &self.event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::SetBreakpoints(
+                        debugger.breakpoints.clone(),
+                    )),
+                );
+            }
+            if debugger.needs_memory() {
+                send_event(
+                    &self.event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::ReportMemory(true)),
+                );
+            }
+        }
+    }
+}
+/// Picks a palette preset, or both colors for the custom palette.
+fn palette_ui(ui: &mut egui::Ui, preset: &mut PalettePreset, palette: &mut Palette) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("palette")
+            .selected_text(preset.to_string())
+            .show_ui(ui, |ui| {
+                for option in PalettePreset::ALL {
+                    ui.selectable_value(preset, option, option.to_string());
+                }
+            });
+        match preset.palette() {
+            Some(preset_palette) => *palette = preset_palette,
+            None => {
+                ui.color_edit_button_srgba(&mut palette.foreground)
+                    .on_hover_text("lit pixels");
+                ui.color_edit_button_srgba(&mut palette.background)
+                    .on_hover_text("background");
+            }
+        }
+    });
+}
+/// Field for the port a server listens on or a client connects to.
+fn port_ui(ui: &mut egui::Ui, port: &mut u16) {
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(port));
+        ui.label("port");
+    });
+}
+/// File dialog for replays.
+fn replay_dialog() -> rfd::FileDialog {
+    rfd::FileDialog::new().add_filter("replay", &[REPLAY_EXTENSION])
+}
+/// File dialog for save-states, opened in the save-state directory.
+fn save_state_dialog() -> rfd::FileDialog {
+    let dialog = rfd::FileDialog::new().add_filter("save-state", &[SAVE_STATE_EXTENSION]);
+    match paths::save_dir() {
+        Some(dir) => dialog.set_directory(dir),
+        None => dialog,
     }
 }
 impl Debugger {
-    fn ui(&self, ctx: &Context, event_bus: &EventLoopProxy<AppEvents>) {
+    /// Whether a window showing the memory is open, so the emulator has to report it.
+    fn needs_memory(&self) -> bool {
+        self.show_memory || self.show_disassembly
+    }
+    /// Drops the oldest entries of the instruction and register history until they hold at most `limit` entries.
+    fn trim_history(&mut self, limit: usize) {
+        let excess = self.op_hist.len().saturating_sub(limit);
+        self.op_hist.drain(..excess);
+        self.reg_hist.drain(..excess);
+        self.op_hist_offset += excess;
+    }
+    /// Drops the `count` newest entries of the history, e.g. after stepping back.
+    pub fn drop_newest(&mut self, count: usize) {
+        let len = self.op_hist.len().saturating_sub(count);
+        self.op_hist.truncate(len);
+        self.reg_hist.truncate(len);
+    }
+    fn clear_history(&mut self) {
+        self.op_hist.clear();
+        self.reg_hist.clear();
+        self.op_hist_offset = 0;
+        self.frame_history.clear();
+        self.frames_back = 0;
+    }
+    /// Formats the current state and the most recent history as text for bug reports.
+    fn state_report(&self) -> String {
+        let state = &self.current;
+        let mut report = format!(
+            "pc: {:04x}\ni: {:04x}\nop: {:04x} {}\n",
+            state.pc,
+            state.i,
+            state.op,
+            map_op(state.op)
+        );
+        for (i, reg) in state.reg.iter().enumerate() {
+            report += &format!("v{i:x}: {reg:02x}\n");
+        }
+        report += "history:\n";
+        let skip = self.op_hist.len().saturating_sub(REPORTED_HISTORY);
+        for (i, op) in self.op_hist.iter().enumerate().skip(skip) {
+            let index = self.op_hist_offset + i;
+            report += &format!("{index}: {op:04x} {}\n", map_op(*op));
+        }
+        report
+    }
+    /// Approximate memory used by the instruction and register history in bytes.
+    fn history_memory(&self) -> usize {
+        self.op_hist.len() * mem::size_of::<u16>()
+            + self.reg_hist.len() * mem::size_of::<[u8; 16]>()
+    }
+    /// Memory used by the frames of the frame viewer in bytes.
+    fn frame_history_memory(&self) -> usize {
+        self.frame_history
+            .iter()
+            .map(|(_, frame)| frame.len())
+            .sum()
+    }
+    /// Plots the chosen registers over the history.
+    fn register_plot_ui(&mut self, ctx: &Context) {
+        egui::Window::new("Register plot").show(ctx, |ui| {
+            let selected: Vec<String> = (0..16)
+                .filter(|&reg| self.plotted_registers[reg])
+                .map(|reg| format!("v{reg:x}"))
+                .collect();
+            ComboBox::from_label("registers")
+                .selected_text(selected.join(", "))
+                .show_ui(ui, |ui| {
+                    for (reg, plotted) in self.plotted_registers.iter_mut().enumerate() {
+                        ui.checkbox(plotted, format!("v{reg:x}"));
+                    }
+                });
+            Plot::new("register plot")
+                .height(200.)
+                .legend(Legend::default())
+                .show(ui, |plot_ui| {
+                    for reg in (0..16).filter(|&reg| self.plotted_registers[reg]) {
+                        let points: PlotPoints = self
+                            .reg_hist
+                            .iter()
+                            .enumerate()
+                            .map(|(i, regs)| [(self.op_hist_offset + i) as f64, regs[reg] as f64])
+                            .collect();
+                        plot_ui.line(Line::new(points).name(format!("v{reg:x}")));
+                    }
+                });
+        });
+    }
+    /// Stores a copy of the display, dropping the oldest frame once [`FRAME_HISTORY_LEN`] are stored.
+    pub fn push_frame(&mut self, frame: &[u8], resolution: Resolution) {
+        if self.frame_history.len() == FRAME_HISTORY_LEN {
+            self.frame_history.pop_front();
+        }
+        self.frame_history.push_back((resolution, frame.to_vec()));
+    }
+    /// Shows the debugger, with why the emulator stopped if it did.
+    fn ui(&mut self, ctx: &Context, event_bus: &EventLoopProxy<AppEvents>, stopped: Option<&str>) {
         let state = &self.current;
+        let number_format = &mut self.number_format;
+        let mut copy_state = false;
         egui::Window::new("Debugger").show(ctx, |ui| {
+            if let Some(reason) = stopped {
+                ui.colored_label(Color32::RED, format!("stopped: {reason}"));
+            }
+            if let Some(addr) = self.run_to_missed {
+                ui.colored_label(
+                    Color32::RED,
+                    format!("didn't reach {addr:04x} within {RUN_TO_MAX_CYCLES} instructions"),
+                );
+            }
+            ui.horizontal(|ui| {
+                ui.radio_value(number_format, NumberFormat::Hex, "Hex");
+                ui.radio_value(number_format, NumberFormat::Dec, "Dec");
+                ui.radio_value(number_format, NumberFormat::Both, "Both");
+            });
+            let number_format = *number_format;
+            ui.horizontal(|ui| {
+                if ui
+                    .checkbox(&mut self.freeze_timers, "freeze timers")
+                    .changed()
+                {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::FreezeTimers(self.freeze_timers)),
+                    );
+                }
+                if ui.checkbox(&mut self.halt_cpu, "halt cpu").changed() {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::HaltCpu(self.halt_cpu)),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_register_plot, "register plot");
+                let memory_toggled = ui.checkbox(&mut self.show_memory, "memory").changed();
+                let disassembly_toggled = ui
+                    .checkbox(&mut self.show_disassembly, "disassembly")
+                    .changed();
+                if memory_toggled || disassembly_toggled {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::ReportMemory(
+                            self.show_memory || self.show_disassembly,
+                        )),
+                    );
+                }
+                ui.checkbox(&mut self.or_draw_mode, "OR draw mode")
+                    .on_hover_text(
+                    "Sprites never erase pixels, so the shown display no longer matches the game",
+                );
+                copy_state = ui.button("copy state").clicked();
+                if ui.button("dump memory").clicked() {
+                    if let Some(dir) = paths::dump_dir() {
+                        send_event(
+                            event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::DumpMemory(dir)),
+                        );
+                    }
+                }
+                let trace_text = if self.tracing {
+                    "stop trace log"
+                } else {
+                    "trace log..."
+                };
+                if ui
+                    .button(trace_text)
+                    .on_hover_text("append every executed instruction to a text file")
+                    .clicked()
+                {
+                    let path = if self.tracing {
+                        None
+                    } else {
+                        rfd::FileDialog::new()
+                            .add_filter("trace log", &["log", "txt"])
+                            .save_file()
+                    };
+                    self.tracing = path.is_some();
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::SetTrace(path)),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("prev").clicked() {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::StepBack(1)),
+                    );
+                }
+                if ui.button("prev 5").clicked() {
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::StepBack(5)),
+                    );
+                }
+            });
             if ui.button("next").clicked() {
-                event_bus
-                    .send_event(AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(1)))
-                    .unwrap();
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(1)),
+                );
             }
             if ui.button("next 5").clicked() {
-                event_bus
-                    .send_event(AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(5)))
-                    .unwrap();
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(5)),
+                );
             }
             if ui.button("next 10").clicked() {
-                event_bus
-                    .send_event(AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(10)))
-                    .unwrap();
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(10)),
+                );
             }
             if ui.button("next 50").clicked() {
-                event_bus
-                    .send_event(AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(50)))
-                    .unwrap();
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::NextDebugCycle(50)),
+                );
             }
-            let label = |v, name| format!("{name}: [{v}] ({v:x})");
-            ui.label(label(state.pc, "pc"));
+            ui.horizontal(|ui| {
+                ui.label("jump to");
+                ui.add(egui::TextEdit::singleline(&mut self.jump_input).desired_width(40.))
+                    .on_hover_text("address in hex the cpu continues at");
+                let pc = u16::from_str_radix(self.jump_input.trim(), 16)
+                    .ok()
+                    .filter(|&pc| pc.is_multiple_of(2) && (pc as usize) < MEMORY_SIZE);
+                match pc {
+                    Some(pc) => {
+                        if ui.button("set pc").clicked() {
+                            send_event(
+                                event_bus,
+                                AppEvents::EmulatorEvent(EmulatorEvents::SetPc(pc)),
+                            );
+                        }
+                    }
+                    None if self.jump_input.trim().is_empty() => {}
+                    None => {
+                        ui.colored_label(Color32::RED, "has to be an even address below 1000");
+                    }
+                }
+            });
+            ui.separator();
+            let mut breakpoints_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("breakpoint");
+                ui.add(egui::TextEdit::singleline(&mut self.breakpoint_input).desired_width(40.))
+                    .on_hover_text("address in hex");
+                if ui.button("add").clicked() {
+                    match u16::from_str_radix(self.breakpoint_input.trim(), 16) {
+                        Ok(addr) if !self.breakpoints.contains(&addr) => {
+                            self.breakpoints.push(addr);
+                            self.breakpoints.sort_unstable();
+                            breakpoints_changed = true;
+                        }
+                        _ => {}
+                    }
+                }
+                if ui
+                    .button("continue")
+                    .on_hover_text("run without the debugger until a breakpoint is reached")
+                    .clicked()
+                {
+                    if self.halt_cpu {
+                        self.halt_cpu = false;
+                        send_event(
+                            event_bus,
+                            AppEvents::EmulatorEvent(EmulatorEvents::HaltCpu(false)),
+                        );
+                    }
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::SetDebug(false)),
+                    );
+                }
+            });
+            self.breakpoints.retain(|&addr| {
+                ui.horizontal(|ui| {
+                    let text = format!("{addr:04x}");
+                    if addr == state.pc {
+                        ui.colored_label(Color32::RED, text);
+                    } else {
+                        ui.label(text);
+                    }
+                    let removed = ui.small_button("remove").clicked();
+                    breakpoints_changed |= removed;
+                    !removed
+                })
+                .inner
+            });
+            if breakpoints_changed {
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::SetBreakpoints(
+                        self.breakpoints.clone(),
+                    )),
+                );
+            }
+            ui.separator();
+            let label = |v, name| format!("{name}: {}", number_format.format(v));
+            ui.label(label(state.pc, "pc".into()));
             ui.label(format!(
-                "{name}: [{op}] ({op:x}) {desc}",
-                name = "op",
-                op = state.op,
+                "{op} {desc}",
+                op = label(state.op, "op".into()),
                 desc = map_op(state.op)
             ));
-            ui.label(label(state.i, "i"));
+            ui.label(label(state.i, "i".into()));
+            ui.label(label(state.delay_timer as u16, "delay".into()));
+            ui.horizontal(|ui| {
+                ui.label(label(state.sound_timer as u16, "sound".into()));
+                if state.sound_timer > 0 {
+                    ui.colored_label(Color32::YELLOW, "beeping");
+                }
+            });
             ui.separator();
-            let label = |v, name| format!("{name}: [{v}] ({v:x})");
             for i in 0..state.reg.len() {
                 let name = i.to_string();
                 ui.label(label(state.reg[i] as u16, name));
             }
         });
+        if copy_state {
+            let report = self.state_report();
+            ctx.output_mut(|o| o.copied_text = report);
+        }
+        let number_format = self.number_format;
+        egui::Window::new("Frames").show(ctx, |ui| {
+            let Some(newest) = self.frame_history.len().checked_sub(1) else {
+                ui.label("nothing drawn yet");
+                return;
+            };
+            self.frames_back = self.frames_back.min(newest);
+            ui.add(Slider::new(&mut self.frames_back, 0..=newest).text("frames back"));
+            let (resolution, frame) = &self.frame_history[newest - self.frames_back];
+            let image = egui::ColorImage::from_rgba_unmultiplied(
+                [resolution.width(), resolution.height()],
+                frame,
+            );
+            let texture = ctx.load_texture("frame history", image, egui::TextureOptions::NEAREST);
+            // high resolution frames are shown at the same size as low resolution ones
+            let scale = 4. * SCREEN_WIDTH as f32 / resolution.width() as f32;
+            ui.image(&texture, texture.size_vec2() * scale);
+        });
+        egui::Window::new("Stack").show(ctx, |ui| {
+            let stack = &self.current.stack;
+            ui.heading(format!("depth: {}", stack.len()));
+            for (depth, addr) in stack.iter().enumerate().rev() {
+                ui.label(format!(
+                    "{depth}: return to {}",
+                    number_format.format(*addr)
+                ));
+            }
+        });
+        if self.show_register_plot {
+            self.register_plot_ui(ctx);
+        }
+        if self.show_memory {
+            if let Some((addr, value)) = self.memory_view.ui(ctx, &self.current) {
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::SetMemory { addr, value }),
+                );
+            }
+        }
+        let action = self
+            .show_disassembly
+            .then(|| {
+                self.disassembly_view
+                    .ui(ctx, &self.current, &mut self.breakpoints)
+            })
+            .flatten();
+        match action {
+            Some(DisassemblyAction::ToggledBreakpoint) => send_event(
+                event_bus,
+                AppEvents::EmulatorEvent(EmulatorEvents::SetBreakpoints(self.breakpoints.clone())),
+            ),
+            Some(DisassemblyAction::RunTo(addr)) => {
+                self.run_to_missed = None;
+                if self.halt_cpu {
+                    self.halt_cpu = false;
+                    send_event(
+                        event_bus,
+                        AppEvents::EmulatorEvent(EmulatorEvents::HaltCpu(false)),
+                    );
+                }
+                send_event(
+                    event_bus,
+                    AppEvents::EmulatorEvent(EmulatorEvents::RunTo(addr)),
+                );
+            }
+            None => {}
+        }
         egui::Window::new("History op").show(ctx, |ui| {
-            let label = |v, name| format!("{name}: [{v}] ({v:x})");
+            let label = |v, name| format!("{name}: {}", number_format.format(v));
+            // the newest entry is the instruction at the current pc
+            let at_breakpoint = self.breakpoints.contains(&self.current.pc);
             ScrollArea::vertical().max_height(800.).show(ui, |ui| {
                 for i in (0..self.op_hist.len()).rev() {
-                    ui.label(label(self.op_hist[i], i.to_string()));
+                    let index = self.op_hist_offset + i;
+                    let text = label(self.op_hist[i], index.to_string());
+                    if at_breakpoint && i + 1 == self.op_hist.len() {
+                        ui.colored_label(Color32::RED, text);
+                    } else {
+                        ui.label(text);
+                    }
                 }
             });
         });
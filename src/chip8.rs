@@ -1,175 +1,892 @@
 use std::{
+    collections::VecDeque,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{mpsc::Receiver, Arc, RwLock},
     thread,
     time::{Duration, Instant},
 };
 
-use egui::Color32;
-use pixels::Pixels;
 use serde::{Deserialize, Serialize};
-use winit::event_loop::EventLoopProxy;
 
 use crate::{
-    display_bus::{AppEvents, DebugState},
+    display_bus::{AppEvents, DebugState, EmulatorStats, EventSink},
     io::InputState,
+    paths,
 };
 
-use self::hardware::{Generation, Hardware};
+use self::audio::Beeper;
+use self::clock::{Clock, SystemClock, TimerSchedule, TIMER_HZ, TIMER_PERIOD};
+use self::hardware::{Generation, Hardware, Quirks, MEMORY_SIZE};
+use self::profile::Profile;
+use self::replay::{Replay, ReplaySession};
+use self::rom::RomError;
+use self::save_state::SaveState;
+use self::screen::Resolution;
+use self::trace::{Trace, TraceLog};
+pub mod asm;
+pub mod audio;
+pub mod clock;
+pub mod coverage;
+pub mod debug_map;
 pub mod hardware;
+pub mod profile;
+pub mod replay;
+pub mod rom;
+pub mod save_state;
 pub mod screen;
+pub mod trace;
 
 pub const DEFAULT_PROGRAM: &[u8] = include_bytes!("../assets/hello_viki.ch");
+/// Maximum number of events handled between two instructions.
+pub const MAX_EVENTS_PER_DRAIN: usize = 16;
+/// Time between two [`EmulatorStats`] reports to the app.
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// Default number of states kept for stepping back in the debugger.
+/// Each state holds a copy of the memory, so this costs a bit more than 2.5 MiB.
+pub const DEFAULT_REWIND_DEPTH: usize = 600;
+/// Default number of bytes around pc and i the debug state reports while the memory is reported.
+pub const DEFAULT_MEMORY_WINDOW: usize = 512;
+/// Instructions a run to an address executes at most, so loops that never get there stop again.
+pub const RUN_TO_MAX_CYCLES: u64 = 100_000;
 pub struct Chip8 {
-    display_bus: EventLoopProxy<AppEvents>,
-    pixels: Arc<RwLock<Pixels>>,
+    display_bus: Box<dyn EventSink>,
     input: Arc<RwLock<InputState>>,
     hardware: Hardware,
     event_bus: Receiver<EmulatorEvents>,
     config: EmulatorConfig,
+    /// Whether VF was set when it was last reported to the app.
+    flag_set: bool,
+    /// The last executed instructions, written to the crash log on errors.
+    trace: Trace,
+    /// Log file of the executed instructions while tracing.
+    trace_log: Option<TraceLog>,
+    /// Only the first error is written to the crash log.
+    crash_logged: bool,
+    clock: Box<dyn Clock>,
+    /// Number of instructions executed, excluding stalled draws.
+    instructions: u64,
+    started: Instant,
+    /// Counts the executed opcodes while profiling.
+    profile: Option<Profile>,
+    /// Recording or playback of the keys, which replaces the live input of the program.
+    replay: Option<ReplaySession>,
+    /// When the stats were last sent to the app.
+    stats_sent: Instant,
+    /// Value of `instructions` when the stats were last sent.
+    stats_instructions: u64,
+    /// Ticks the timers outside of the debugger.
+    timers: TimerSchedule,
+    /// Start of the timer period the cpu currently runs the instructions of.
+    period_start: Instant,
+    /// Instructions left to execute in the current timer period.
+    cycle_budget: f32,
+    /// Plays the buzzer, `None` if there's no audio output.
+    beeper: Option<Beeper>,
+    /// The running program, restarted on reset. Empty if the rom couldn't be loaded.
+    program: Vec<u8>,
+    /// States before the most recent debugger steps, newest last.
+    rewind: VecDeque<SaveState>,
 }
 
 pub struct EmulatorConfig {
-    color: Color32,
     generation: Generation,
+    quirks: Quirks,
     runner: Chip8Runner,
     path: Option<PathBuf>,
-    fps: u32,
+    /// Instructions executed per second, independent of the 60 Hz of the timers.
+    cpu_hz: u32,
+    /// Seed of the random numbers, random if `None`. Resets start over with the same numbers.
+    seed: Option<u64>,
+    /// Stops the delay and sound timers from counting down.
+    freeze_timers: bool,
+    /// Stops the cpu from executing instructions while the timers keep running.
+    halt_cpu: bool,
+    /// Report changes of the VF register to the app.
+    report_flag: bool,
+    /// Include the memory in the debug state, which is only needed while the app shows it.
+    report_memory: bool,
+    /// Number of bytes around pc and i in the reported memory.
+    memory_window: usize,
+    /// Halt the cpu before the first instruction executes.
+    start_paused: bool,
+    /// Keep the buzzer silent.
+    muted: bool,
+    /// Stops both the cpu and the timers until resumed.
+    paused: bool,
+    /// Addresses that switch to the debugger before the instruction there executes.
+    breakpoints: Vec<u16>,
+    /// Set when leaving the debugger, so the breakpoint it stopped at doesn't trigger again.
+    resuming: bool,
+    /// Temporary breakpoint of a run to an address and the instructions left before giving up.
+    /// Removed once reached.
+    run_to: Option<(u16, u64)>,
+    /// Maximum number of states kept for stepping back. 0 disables stepping back.
+    rewind_depth: usize,
+    /// Multiplier of the cpu and the timer rate, above 1 while fast-forwarding.
+    speed: f32,
 }
 impl EmulatorConfig {
     pub fn new(
-        color: Color32,
         generation: Generation,
+        quirks: Quirks,
         debug: bool,
+        start_paused: bool,
         path: Option<PathBuf>,
-        fps: u32,
+        cpu_hz: u32,
+        seed: Option<u64>,
     ) -> EmulatorConfig {
         Self {
-            color,
             generation,
+            quirks,
             runner: Chip8Runner::new(debug),
             path,
-            fps,
+            cpu_hz,
+            seed,
+            freeze_timers: false,
+            halt_cpu: false,
+            report_flag: false,
+            report_memory: false,
+            memory_window: DEFAULT_MEMORY_WINDOW,
+            start_paused,
+            muted: false,
+            paused: false,
+            breakpoints: Vec::new(),
+            resuming: false,
+            run_to: None,
+            rewind_depth: DEFAULT_REWIND_DEPTH,
+            speed: 1.,
         }
     }
 }
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum EmulatorEvents {
-    ChangeColor(Color32),
-    FpsChange(u32),
+    /// Sets the instructions executed per second.
+    SetCpuHz(u32),
+    /// Runs the cpu and the timers this many times as fast, like while the turbo key is held.
+    SetSpeedMultiplier(f32),
     NextDebugCycle(usize),
+    /// Restores the state from before the given number of debugger steps.
+    /// Goes back to the oldest kept state if there are fewer.
+    StepBack(usize),
+    SetRewindDepth(usize),
     SetDebug(bool),
+    FreezeTimers(bool),
+    HaltCpu(bool),
+    ReportFlag(bool),
+    ReportMemory(bool),
+    /// Sets the number of bytes of the reported memory, rounded up to whole rows of 16 bytes.
+    SetMemoryWindow(usize),
+    SetMuted(bool),
+    /// Stops executing instructions and counting down the timers while set.
+    Pause(bool),
+    SetBreakpoints(Vec<u16>),
+    /// Writes `value` to the memory at `addr`, only while the debugger or the pause stops the cpu.
+    SetMemory {
+        addr: u16,
+        value: u8,
+    },
+    /// Continues execution at the address, only while the debugger or the pause stops the cpu.
+    SetPc(u16),
+    /// Runs outside of the debugger until the pc reaches the address, giving up after [`RUN_TO_MAX_CYCLES`].
+    RunTo(u16),
+    SetShiftQuirk(bool),
+    SetJumpQuirk(bool),
+    SetClipQuirk(bool),
+    SetLoadStoreQuirk(bool),
+    SetDisplayWaitQuirk(bool),
+    /// Replaces the running program without restarting the emulator.
+    LoadRom(Vec<u8>),
+    /// Restarts the running program, keeping the settings of the emulator.
+    Reset,
+    /// Appends every executed instruction to the log file, or stops logging.
+    SetTrace(Option<PathBuf>),
+    /// Restarts the program and records the keys of every frame, written to the file once it stops.
+    RecordReplay(PathBuf),
+    /// Restarts the program of the replay in the file and plays back its keys.
+    PlayReplay(PathBuf),
+    /// Stops recording or playing back a replay.
+    StopReplay,
+    /// Starts counting the executed opcodes, or stops and drops the counts.
+    SetProfiling(bool),
+    /// Starts the opcode counts over.
+    ResetProfile,
+    /// Writes the memory to a file in the given directory.
+    DumpMemory(PathBuf),
+    /// Writes a save-state of the hardware to the file.
+    SaveState(PathBuf),
+    /// Continues from the save-state in the file.
+    LoadState(PathBuf),
     QuitEmulator,
     DisplaySynced,
 }
+/// Reads the rom at `path`, or the built-in demo if there's no path.
+fn read_program(path: Option<&Path>) -> Result<Vec<u8>, RomError> {
+    match path {
+        Some(path) => rom::read_rom(path),
+        None => Ok(DEFAULT_PROGRAM.to_vec()),
+    }
+}
+/// Returns the first address of the `len` bytes of memory reported around `pc` and `i`.
+///
+/// The window starts at a row of 16 bytes and shows both pointers if they fit, otherwise just `pc`.
+fn memory_window_start(pc: u16, i: u16, len: usize) -> usize {
+    let rows = len / 16;
+    let (low, high) = (pc.min(i) as usize, pc.max(i) as usize + 2);
+    let (first_row, end_row) = (low / 16, high.div_ceil(16));
+    let start_row = if end_row - first_row <= rows {
+        first_row.saturating_sub((rows - (end_row - first_row)) / 2)
+    } else {
+        (pc as usize / 16).saturating_sub(rows / 2)
+    };
+    start_row.min((MEMORY_SIZE - len) / 16) * 16
+}
 impl Chip8 {
     pub fn new(
-        display_bus: EventLoopProxy<AppEvents>,
-        pixels: Arc<RwLock<Pixels>>,
+        display_bus: impl EventSink + 'static,
         input: Arc<RwLock<InputState>>,
         event_bus: Receiver<EmulatorEvents>,
         emulator_config: EmulatorConfig,
     ) -> Chip8 {
         let mut hardware = Hardware::default();
         hardware.set_generation(emulator_config.generation);
-        let program = emulator_config
-            .path
-            .as_ref()
-            .and_then(|path| fs::read(path).ok())
-            .unwrap_or(DEFAULT_PROGRAM.to_vec());
-        hardware.load_program(&program);
+        hardware.set_quirks(emulator_config.quirks);
+        if let Some(seed) = emulator_config.seed {
+            hardware.seed_rng(seed);
+        }
+        let loaded = read_program(emulator_config.path.as_deref()).and_then(|program| {
+            hardware.load_program(&program)?;
+            Ok(program)
+        });
+        let program = match loaded {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("couldn't load rom {:?} with {e}", emulator_config.path);
+                // running something else than the chosen rom would only confuse
+                hardware.halt();
+                display_bus.send(AppEvents::EmulatorError(format!("couldn't load rom: {e}")));
+                Vec::new()
+            }
+        };
+        let clock = SystemClock;
+        let now = clock.now();
         Chip8 {
             event_bus,
-            display_bus,
-            pixels,
+            display_bus: Box::new(display_bus),
             hardware,
             input,
             config: emulator_config,
+            flag_set: false,
+            trace: Trace::default(),
+            trace_log: None,
+            crash_logged: false,
+            clock: Box::new(clock),
+            instructions: 0,
+            started: now,
+            profile: None,
+            replay: None,
+            stats_sent: now,
+            stats_instructions: 0,
+            timers: TimerSchedule::new(now),
+            period_start: now,
+            cycle_budget: 0.,
+            beeper: Beeper::new()
+                .inspect_err(|e| eprintln!("couldn't open audio output with {e}"))
+                .ok(),
+            program,
+            rewind: VecDeque::new(),
         }
     }
     pub fn run_hardware_cycle(&mut self) {
+        let pc = self.hardware.pc;
         let instr = self.hardware.fetch();
-        self.hardware
-            .decode(instr, &self.display_bus, &self.pixels, &self.input);
+        self.trace.push(pc, instr);
+        let before = self
+            .trace_log
+            .is_some()
+            .then_some((self.hardware.i, self.hardware.registers));
+        let input = self
+            .replay
+            .as_ref()
+            .map_or(&self.input, |session| session.input());
+        let halted = self.hardware.halted();
+        let result = self.hardware.decode(instr, input);
+        if let (Some(log), Some((i, registers))) = (&mut self.trace_log, before) {
+            // a stalled draw runs again, so it's logged once it went through
+            if !self.hardware.stalled() {
+                if let Err(e) = log.write(pc, instr, i, &registers) {
+                    eprintln!("couldn't write trace log with {e}");
+                    self.trace_log = None;
+                }
+            }
+        }
+        match result {
+            Ok(commands) => {
+                for command in commands {
+                    self.display_bus.send(command.into());
+                }
+                if !halted && self.hardware.halted() {
+                    self.display_bus.send(AppEvents::ProgramExited);
+                }
+                if !self.hardware.stalled() {
+                    self.instructions += 1;
+                    if let Some(profile) = &mut self.profile {
+                        profile.record(instr);
+                    }
+                }
+            }
+            Err(e) => {
+                let error = format!("{e} at {pc:04x}");
+                eprintln!("{error}");
+                if !self.crash_logged {
+                    self.crash_logged = true;
+                    self.write_crash_log(&error);
+                }
+                // the program is most likely executing garbage from here on
+                self.hardware.halt();
+                self.display_bus.send(AppEvents::EmulatorError(error));
+            }
+        }
+        if self.config.report_flag {
+            self.report_flag();
+        }
+    }
+    /// Sends the stats to the app once every [`STATS_INTERVAL`].
+    fn report_stats(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.stats_sent);
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+        let executed = self.instructions.saturating_sub(self.stats_instructions);
+        self.stats_sent = now;
+        self.stats_instructions = self.instructions;
+        let stats = EmulatorStats {
+            instructions: self.instructions,
+            uptime: now.saturating_duration_since(self.started),
+            instructions_per_second: (executed as f64 / elapsed.as_secs_f64()).round() as u64,
+            target_hz: self.config.cpu_hz,
+        };
+        self.display_bus.send(AppEvents::EmulatorStats(stats));
+        // the log is complete up to the last second even if the emulator doesn't quit cleanly
+        if let Some(log) = &mut self.trace_log {
+            if let Err(e) = log.flush() {
+                eprintln!("couldn't write trace log with {e}");
+                self.trace_log = None;
+            }
+        }
+        if let Some(profile) = &self.profile {
+            self.display_bus.send(AppEvents::OpcodeProfile {
+                counts: profile.counts(),
+                total: profile.total(),
+            });
+        }
+    }
+    /// Resets the hardware with `program`, keeping the generation and quirks.
+    /// Returns false if the program doesn't fit into memory.
+    fn load_rom(&mut self, program: &[u8]) -> bool {
+        self.stop_replay();
+        let mut hardware = Hardware::default();
+        hardware.set_generation(self.config.generation);
+        hardware.set_quirks(self.hardware.quirks);
+        if let Some(seed) = self.config.seed {
+            hardware.seed_rng(seed);
+        }
+        if let Err(e) = hardware.load_program(program) {
+            eprintln!("couldn't load rom with {e}");
+            self.display_bus
+                .send(AppEvents::EmulatorError(format!("couldn't load rom: {e}")));
+            return false;
+        }
+        self.hardware = hardware;
+        self.program = program.to_vec();
+        self.trace = Trace::default();
+        self.crash_logged = false;
+        self.config.run_to = None;
+        self.instructions = 0;
+        if self.profile.is_some() {
+            self.profile = Some(Profile::default());
+        }
+        // the states belong to the previous run
+        self.rewind.clear();
+        self.started = self.clock.now();
+        self.timers = TimerSchedule::new(self.started);
+        // programs start in the low resolution, which also clears the screen
+        self.display_bus
+            .send(AppEvents::SetResolution(Resolution::Low));
+        true
+    }
+    fn record_replay(&mut self, path: PathBuf) {
+        if self.program.is_empty() {
+            eprintln!("there's no program to record");
+            return;
+        }
+        let replay = Replay {
+            program: self.program.clone(),
+            generation: self.config.generation,
+            quirks: self.hardware.quirks,
+            seed: self.config.seed.unwrap_or_else(|| fastrand::u64(..)),
+            cycles_per_frame: (self.config.cpu_hz / TIMER_HZ).max(1),
+            frames: Vec::new(),
+        };
+        self.start_replay(replay, Some(path));
+    }
+    fn play_replay(&mut self, replay: Replay) {
+        self.config.generation = replay.generation;
+        self.hardware.quirks = replay.quirks;
+        self.start_replay(replay, None);
+    }
+    /// Restarts the program of `replay` with its seed, recording to `path` or playing the replay back.
+    fn start_replay(&mut self, replay: Replay, path: Option<PathBuf>) {
+        if !self.load_rom(&replay.program) {
+            return;
+        }
+        self.hardware.seed_rng(replay.seed);
+        let mut session = match path {
+            Some(path) => ReplaySession::record(replay, path, self.instructions),
+            None => ReplaySession::play(replay, self.instructions),
+        };
+        if session.next_frame(&self.input, self.instructions) {
+            self.replay = Some(session);
+        } else {
+            self.display_bus.send(AppEvents::ReplayFinished);
+        }
+    }
+    /// Starts the next frame of the replay once the current one ran its instructions, ticking the timers.
+    /// Frames count instructions instead of time, so the playback matches the recording.
+    fn replay_cycle(&mut self) {
+        let Some(session) = &mut self.replay else {
+            return;
+        };
+        if !session.frame_done(self.instructions) {
+            return;
+        }
+        if !self.config.freeze_timers {
+            self.hardware.tick_cpu_clock();
+        }
+        if !session.next_frame(&self.input, self.instructions) {
+            self.replay = None;
+            self.config.paused = true;
+            self.display_bus.send(AppEvents::ReplayFinished);
+        }
+    }
+    /// Stops the replay, writing it to its file if it was recorded.
+    fn stop_replay(&mut self) {
+        if let Some(session) = self.replay.take() {
+            if let Err(e) = session.finish() {
+                eprintln!("couldn't write replay with {e}");
+            }
+        }
+    }
+    /// Keeps `state` for stepping back, dropping the oldest state once there are `rewind_depth`.
+    fn push_rewind(&mut self, state: SaveState) {
+        if self.rewind.len() >= self.config.rewind_depth {
+            self.rewind.pop_front();
+        }
+        self.rewind.push_back(state);
+    }
+    fn step_back(&mut self, steps: usize) {
+        let steps = steps.min(self.rewind.len());
+        let Some(state) = self.rewind.drain(self.rewind.len() - steps..).next() else {
+            return;
+        };
+        self.display_bus.send(AppEvents::Rewound(steps));
+        self.load_state(state);
+    }
+    /// Continues from `state` and redraws the display from its framebuffer.
+    fn load_state(&mut self, state: SaveState) {
+        self.config.generation = state.generation;
+        self.hardware.restore(state);
+        self.trace = Trace::default();
+        self.crash_logged = false;
+        self.timers = TimerSchedule::new(self.clock.now());
+        let framebuffer = self.hardware.framebuffer();
+        let resolution = framebuffer.resolution();
+        // also clears the screen
+        self.display_bus.send(AppEvents::SetResolution(resolution));
+        // a sprite covers 16 columns and 16 rows of the display
+        for x in (0..resolution.width()).step_by(16) {
+            for y in (0..resolution.height()).step_by(16) {
+                let sprite: Vec<u16> = (0..16)
+                    .map(|row| framebuffer.sprite_row(x, y + row))
+                    .collect();
+                if sprite.iter().all(|&row| row == 0) {
+                    continue;
+                }
+                self.display_bus.send(AppEvents::DrawSprite {
+                    sprite,
+                    x: x as u8,
+                    y: y as u8,
+                    wrap: false,
+                });
+            }
+        }
+        if self.runner().is_debug() {
+            self.send_debug_state();
+        }
+    }
+    fn set_memory(&mut self, addr: u16, value: u8) {
+        if !self.runner().is_debug() && !self.config.paused {
+            eprintln!("ignoring the write to {addr:03x} while the cpu is running");
+            return;
+        }
+        let Some(byte) = self.hardware.memory.get_mut(addr as usize) else {
+            eprintln!("ignoring the write to {addr:04x} outside of the memory");
+            return;
+        };
+        *byte = value;
+        self.refresh_debug_state();
+    }
+    fn set_pc(&mut self, pc: u16) {
+        if !self.runner().is_debug() && !self.config.paused {
+            eprintln!("ignoring the jump to {pc:04x} while the cpu is running");
+            return;
+        }
+        if !pc.is_multiple_of(2) || pc as usize >= MEMORY_SIZE {
+            eprintln!("ignoring the jump to {pc:04x}, which isn't an even address in memory");
+            return;
+        }
+        self.hardware.pc = pc;
+        self.refresh_debug_state();
+    }
+    /// Sends the debug state again after it changed without a step.
+    fn refresh_debug_state(&self) {
+        if self.runner().is_debug() {
+            // replaces the newest history entry, since the change isn't a step
+            self.display_bus.send(AppEvents::Rewound(0));
+            self.send_debug_state();
+        }
+    }
+    /// Writes the memory to `dir`, naming the file after the current pc.
+    fn dump_memory(&self, dir: &Path) {
+        let path = dir.join(format!("memory_{:04x}.bin", self.hardware.pc));
+        match fs::write(&path, self.hardware.memory) {
+            Ok(()) => println!("dumped memory to {path:?}"),
+            Err(e) => eprintln!("couldn't dump memory to {path:?} with {e}"),
+        }
+    }
+    /// Writes the recent instructions and the hardware state to the crash log.
+    fn write_crash_log(&self, reason: &str) {
+        let Some(path) = paths::crash_log() else {
+            return;
+        };
+        let mut log = format!("{reason}\n\n{}\ntrace:\n", self.hardware.state_report());
+        for entry in self.trace.iter() {
+            log += &format!("{:04x}: {:04x}\n", entry.pc, entry.op);
+        }
+        match fs::write(&path, log) {
+            Ok(()) => eprintln!("wrote crash log to {path:?}"),
+            Err(e) => eprintln!("couldn't write crash log to {path:?} with {e}"),
+        }
     }
+    /// Turns the buzzer on while the sound timer runs and the emulator isn't paused or muted.
+    fn update_beeper(&self) {
+        let Some(beeper) = &self.beeper else {
+            return;
+        };
+        let paused = self.hardware.halted()
+            || self.config.paused
+            || self.config.halt_cpu
+            || self.config.freeze_timers
+            || !self.runner().can_run();
+        beeper.set_beeping(self.hardware.is_beeping() && !paused && !self.config.muted);
+    }
+    /// Notifies the app if VF changed between set and unset.
+    fn report_flag(&mut self) {
+        let flag_set = self.hardware.registers[15] != 0;
+        if flag_set != self.flag_set {
+            self.flag_set = flag_set;
+            self.display_bus.send(AppEvents::FlagChanged(flag_set));
+        }
+    }
+    /// Handles up to [`MAX_EVENTS_PER_DRAIN`] pending events.
+    /// Returns as soon as the emulator should quit.
     pub fn handle_event(&mut self) -> Quit {
-        if let Ok(event) = self.event_bus.try_recv() {
-            match event {
-                EmulatorEvents::ChangeColor(c) => {
-                    self.config.color = c;
-                    if let Ok(mut pixels) = self.pixels.write() {
-                        pixels
-                            .frame_mut()
-                            .chunks_exact_mut(4)
-                            .filter(|c| c != &[0, 0, 0, 0])
-                            .for_each(|c| c.copy_from_slice(&self.config.color.to_array()));
-                    }
+        for _ in 0..MAX_EVENTS_PER_DRAIN {
+            let Ok(event) = self.event_bus.try_recv() else {
+                break;
+            };
+            if matches!(self.handle_single_event(event), Quit::True) {
+                return Quit::True;
+            }
+        }
+        Quit::False
+    }
+    fn handle_single_event(&mut self, event: EmulatorEvents) -> Quit {
+        match event {
+            EmulatorEvents::NextDebugCycle(count) => {
+                if let Chip8RunnerKind::DebugRunner { cycles_to_run } = &mut self.config.runner.kind
+                {
+                    *cycles_to_run += count;
                 }
-                EmulatorEvents::NextDebugCycle(count) => {
-                    if let Chip8RunnerKind::DebugRunner { cycles_to_run } =
-                        &mut self.config.runner.kind
-                    {
-                        *cycles_to_run += count;
-                    }
+            }
+            EmulatorEvents::StepBack(steps) => self.step_back(steps),
+            EmulatorEvents::SetRewindDepth(depth) => {
+                self.config.rewind_depth = depth;
+                let excess = self.rewind.len().saturating_sub(depth);
+                self.rewind.drain(..excess);
+            }
+            EmulatorEvents::QuitEmulator => return Quit::True,
+            EmulatorEvents::DisplaySynced => self.hardware.sync_display(),
+            EmulatorEvents::SetCpuHz(cpu_hz) => self.config.cpu_hz = cpu_hz.max(1),
+            EmulatorEvents::SetSpeedMultiplier(speed) if speed.is_finite() && speed > 0. => {
+                self.config.speed = speed;
+            }
+            EmulatorEvents::SetSpeedMultiplier(speed) => {
+                eprintln!("ignoring invalid speed multiplier {speed}");
+            }
+            EmulatorEvents::FreezeTimers(freeze) => self.config.freeze_timers = freeze,
+            EmulatorEvents::HaltCpu(halt) => self.config.halt_cpu = halt,
+            EmulatorEvents::SetShiftQuirk(shift_uses_vy) => {
+                self.hardware.quirks.shift_uses_vy = shift_uses_vy;
+            }
+            EmulatorEvents::SetJumpQuirk(jump_uses_vx) => {
+                self.hardware.quirks.jump_uses_vx = jump_uses_vx;
+            }
+            EmulatorEvents::SetClipQuirk(clip_sprites) => {
+                self.hardware.quirks.clip_sprites = clip_sprites;
+            }
+            EmulatorEvents::SetLoadStoreQuirk(increments_i) => {
+                self.hardware.quirks.load_store_increments_i = increments_i;
+            }
+            EmulatorEvents::SetDisplayWaitQuirk(display_wait) => {
+                self.hardware.quirks.display_wait = display_wait;
+            }
+            EmulatorEvents::DumpMemory(dir) => self.dump_memory(&dir),
+            EmulatorEvents::SaveState(path) => {
+                if let Err(e) = save_state::write(&path, &self.hardware.save_state()) {
+                    eprintln!("couldn't save state to {path:?} with {e}");
                 }
-                EmulatorEvents::QuitEmulator => return Quit::True,
-                EmulatorEvents::DisplaySynced => self.hardware.display_sync = true,
-                EmulatorEvents::FpsChange(fps) => self.config.fps = fps,
-                EmulatorEvents::SetDebug(debug) => {
-                    if debug && self.config.runner.is_debug() {
-                        return Quit::False;
-                    }
-                    self.config.runner = Chip8Runner::new(debug);
+            }
+            EmulatorEvents::LoadState(path) => match save_state::read(&path) {
+                Ok(state) => self.load_state(state),
+                Err(e) => eprintln!("couldn't load state {path:?} with {e}"),
+            },
+            EmulatorEvents::LoadRom(program) => {
+                self.load_rom(&program);
+            }
+            // there's nothing to restart if the rom couldn't be loaded
+            EmulatorEvents::Reset if self.program.is_empty() => {}
+            EmulatorEvents::Reset => {
+                self.load_rom(&self.program.clone());
+            }
+            EmulatorEvents::SetTrace(path) => {
+                // dropping the previous log flushes it
+                self.trace_log = path.and_then(|path| {
+                    TraceLog::open(path.clone())
+                        .inspect_err(|e| eprintln!("couldn't open trace log {path:?} with {e}"))
+                        .ok()
+                });
+            }
+            EmulatorEvents::RecordReplay(path) => self.record_replay(path),
+            EmulatorEvents::PlayReplay(path) => match replay::read(&path) {
+                Ok(replay) => self.play_replay(replay),
+                Err(e) => eprintln!("couldn't read replay {path:?} with {e}"),
+            },
+            EmulatorEvents::StopReplay => self.stop_replay(),
+            EmulatorEvents::SetProfiling(profiling) => {
+                self.profile = profiling.then(Profile::default);
+            }
+            EmulatorEvents::ResetProfile => {
+                if self.profile.is_some() {
+                    self.profile = Some(Profile::default());
+                }
+            }
+            EmulatorEvents::SetMuted(muted) => self.config.muted = muted,
+            EmulatorEvents::SetBreakpoints(breakpoints) => self.config.breakpoints = breakpoints,
+            EmulatorEvents::SetMemory { addr, value } => self.set_memory(addr, value),
+            EmulatorEvents::SetPc(pc) => self.set_pc(pc),
+            EmulatorEvents::RunTo(addr) => {
+                self.config.run_to = Some((addr, RUN_TO_MAX_CYCLES));
+                if self.config.runner.is_debug() {
+                    self.config.runner = Chip8Runner::new(false);
+                    self.config.resuming = true;
+                    self.timers = TimerSchedule::new(self.clock.now());
                 }
             }
+            EmulatorEvents::Pause(paused) => {
+                self.config.paused = paused;
+                // don't catch up on the ticks missed while paused
+                self.timers = TimerSchedule::new(self.clock.now());
+            }
+            EmulatorEvents::ReportMemory(report) => self.config.report_memory = report,
+            EmulatorEvents::SetMemoryWindow(len) => {
+                self.config.memory_window = len.next_multiple_of(16).clamp(16, MEMORY_SIZE);
+            }
+            EmulatorEvents::ReportFlag(report) => {
+                self.config.report_flag = report;
+                // the app assumes an unset flag until it's told otherwise
+                self.flag_set = false;
+            }
+            EmulatorEvents::SetDebug(debug) => {
+                if debug && self.config.runner.is_debug() {
+                    return Quit::False;
+                }
+                self.config.run_to = None;
+                self.config.runner = Chip8Runner::new(debug);
+                self.config.resuming = !debug;
+                // don't catch up on the ticks missed while debugging
+                self.timers = TimerSchedule::new(self.clock.now());
+            }
         }
         Quit::False
     }
     fn send_debug_state(&self) {
-        let instr = ((self.hardware.memory[self.hardware.pc as usize] as u16) << 8)
-            | self.hardware.memory[self.hardware.pc as usize + 1] as u16;
+        let instr = self.hardware.peek_instr();
         let debug_state = DebugState {
             pc: self.hardware.pc,
             i: self.hardware.i,
             reg: self.hardware.registers,
             op: instr,
+            delay_timer: self.hardware.delay_timer(),
+            sound_timer: self.hardware.sound_timer(),
+            memory_start: 0,
+            memory: Vec::new(),
+            stack: self.hardware.stack().to_vec(),
+        };
+        let debug_state = if self.config.report_memory {
+            let len = self.config.memory_window;
+            let start = memory_window_start(self.hardware.pc, self.hardware.i, len);
+            DebugState {
+                memory_start: start as u16,
+                memory: self.hardware.memory[start..start + len].to_vec(),
+                ..debug_state
+            }
+        } else {
+            debug_state
         };
         self.display_bus
-            .send_event(AppEvents::DebugEmulatorState(debug_state))
-            .unwrap();
+            .send(AppEvents::DebugEmulatorState(debug_state));
     }
     pub fn run(mut self) {
+        if self.config.start_paused {
+            // the debugger shows the first instruction until the cpu is resumed
+            self.config.halt_cpu = true;
+            self.send_debug_state();
+        }
         loop {
-            let now = Instant::now();
+            let now = self.clock.now();
             let quit = self.handle_event();
             if matches!(quit, Quit::True) {
                 return;
             }
+            self.report_stats(now);
+            self.update_beeper();
+            if self.hardware.halted() || self.config.paused {
+                // nothing to execute, only keep listening for events
+                self.clock.sleep(Duration::from_millis(10));
+                continue;
+            }
             if self.runner().can_run() {
                 self.config.runner.advance();
                 if self.runner().is_debug() {
-                    self.run_hardware_cycle();
-                    if self.runner().hardware_clock_tick() {
+                    if !self.debug_step() {
+                        continue;
+                    }
+                    if self.replay.is_some() {
+                        self.replay_cycle();
+                    } else if self.runner().timer_tick_due(self.config.cpu_hz)
+                        && !self.config.freeze_timers
+                    {
                         self.hardware.tick_cpu_clock();
                     }
                     self.send_debug_state();
                 } else {
-                    let frame_time = Duration::from_secs_f32(1. / self.config.fps as f32);
-                    self.run_hardware_cycle();
-                    if self.runner().hardware_clock_tick() {
-                        self.hardware.tick_cpu_clock();
-                        let delta = frame_time.saturating_sub(now.elapsed());
-                        thread::sleep(delta);
+                    if !self.config.halt_cpu {
+                        if self.at_breakpoint() || self.run_to_gave_up() {
+                            self.config.runner = Chip8Runner::new(true);
+                            self.send_debug_state();
+                            continue;
+                        }
+                        self.run_hardware_cycle();
                     }
+                    if self.replay.is_some() {
+                        self.replay_cycle();
+                    } else {
+                        let ticks = self.timers.due_ticks(self.clock.now(), self.config.speed);
+                        if !self.config.freeze_timers {
+                            for _ in 0..ticks {
+                                self.hardware.tick_cpu_clock();
+                            }
+                        }
+                    }
+                    self.pace_cpu();
                 }
             }
         }
     }
+    /// Executes the instruction of a debugger step and keeps the state before it for stepping back.
+    /// Returns false if the step was a draw waiting for the display, which gives the step back.
+    fn debug_step(&mut self) -> bool {
+        if self.config.halt_cpu {
+            return true;
+        }
+        let state = (self.config.rewind_depth > 0).then(|| self.hardware.save_state());
+        self.run_hardware_cycle();
+        if self.hardware.stalled() {
+            // a stalled draw isn't a real step, so it shouldn't use up the step budget
+            self.config.runner.refund();
+            return false;
+        }
+        if let Some(state) = state {
+            self.push_rewind(state);
+        }
+        true
+    }
+    /// Counts an executed cycle and sleeps once the cycles of the timer period ran, so the cpu runs at `cpu_hz`.
+    fn pace_cpu(&mut self) {
+        self.cycle_budget -= 1.;
+        if self.cycle_budget > 0. {
+            return;
+        }
+        let period = TIMER_PERIOD.div_f32(self.config.speed);
+        self.clock.sleep_until_frame_end(self.period_start, period);
+        self.period_start += period;
+        let now = self.clock.now();
+        if now.saturating_duration_since(self.period_start) > period {
+            // too far behind to catch up, like after a pause
+            self.period_start = now;
+        }
+        self.cycle_budget += self.config.cpu_hz as f32 / TIMER_HZ as f32;
+    }
+    /// Returns true if the next instruction is at a breakpoint or the address of a run,
+    /// except for the first one after leaving the debugger.
+    fn at_breakpoint(&mut self) -> bool {
+        if std::mem::take(&mut self.config.resuming) {
+            return false;
+        }
+        let pc = self.hardware.pc;
+        if self.config.run_to.is_some_and(|(addr, _)| addr == pc) {
+            self.config.run_to = None;
+            return true;
+        }
+        self.config.breakpoints.contains(&pc)
+    }
+    /// Counts an instruction of a run to an address.
+    /// Returns true once the run is out of instructions, after telling the app.
+    fn run_to_gave_up(&mut self) -> bool {
+        let Some((addr, cycles_left)) = &mut self.config.run_to else {
+            return false;
+        };
+        *cycles_left -= 1;
+        if *cycles_left > 0 {
+            return false;
+        }
+        self.display_bus.send(AppEvents::RunToMissed(*addr));
+        self.config.run_to = None;
+        true
+    }
     fn runner(&self) -> &Chip8Runner {
         &self.config.runner
     }
 }
+impl Drop for Chip8 {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.write_crash_log("emulator thread panicked");
+        }
+        self.stop_replay();
+    }
+}
 pub struct Chip8Runner {
     kind: Chip8RunnerKind,
     cycles: u32,
@@ -184,9 +901,10 @@ impl Chip8Runner {
     pub fn is_debug(&self) -> bool {
         matches!(self.kind, Chip8RunnerKind::DebugRunner { cycles_to_run: _ })
     }
-    pub fn hardware_clock_tick(&self) -> bool {
-        let hardware_cycles_per_clock_tick = 18;
-        self.cycles % hardware_cycles_per_clock_tick == 0
+    /// Returns true if the timers tick after this cycle when the cpu runs at `cpu_hz`.
+    pub fn timer_tick_due(&self, cpu_hz: u32) -> bool {
+        let cycles_per_tick = (cpu_hz / TIMER_HZ).max(1);
+        self.cycles.is_multiple_of(cycles_per_tick)
     }
     pub fn advance(&mut self) {
         self.kind.advance();
@@ -195,6 +913,11 @@ impl Chip8Runner {
     pub fn can_run(&self) -> bool {
         self.kind.can_run()
     }
+    /// Gives back a debug step that didn't execute a real instruction.
+    pub fn refund(&mut self) {
+        self.kind.refund();
+        self.cycles -= 1;
+    }
 }
 #[derive(Copy, Clone)]
 pub enum Chip8RunnerKind {
@@ -214,6 +937,11 @@ impl Chip8RunnerKind {
             *cycles_to_run -= 1;
         }
     }
+    pub fn refund(&mut self) {
+        if let Self::DebugRunner { cycles_to_run } = self {
+            *cycles_to_run += 1;
+        }
+    }
     pub const fn can_run(self) -> bool {
         match self {
             Self::DebugRunner { cycles_to_run } => cycles_to_run > 0,
@@ -225,3 +953,75 @@ pub enum Quit {
     True,
     False,
 }
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::chip8::clock::DEFAULT_CPU_HZ;
+
+    struct IgnoreEvents;
+    impl EventSink for IgnoreEvents {
+        fn send(&self, _event: AppEvents) {}
+    }
+
+    /// Returns an emulator in the debugger running `program`.
+    fn chip8(program: &[u8]) -> Chip8 {
+        let (_, event_bus) = mpsc::channel();
+        let config = EmulatorConfig::new(
+            Generation::Super,
+            Quirks::for_generation(Generation::Super),
+            true,
+            false,
+            None,
+            DEFAULT_CPU_HZ,
+            Some(0),
+        );
+        let mut chip8 = Chip8::new(IgnoreEvents, Arc::default(), event_bus, config);
+        assert!(chip8.load_rom(program));
+        chip8
+    }
+    #[test]
+    fn reloading_the_rom_forgets_the_states_to_step_back_to() {
+        // v0 = 5, v1 = 6, loop forever
+        let program = [0x60, 0x05, 0x61, 0x06, 0x12, 0x04];
+        let mut chip8 = chip8(&program);
+        chip8.run_hardware_cycle();
+        let state = chip8.hardware.save_state();
+        chip8.run_hardware_cycle();
+        chip8.push_rewind(state);
+        chip8.load_rom(&program);
+        chip8.step_back(1);
+        assert_eq!(chip8.hardware.pc, 0x200);
+        assert_eq!(chip8.hardware.registers()[0], 0);
+    }
+    #[test]
+    fn stalled_draws_dont_use_up_debugger_steps() {
+        // draw the font sprite of 0 at v0, v0
+        let mut chip8 = chip8(&[0xd0, 0x05]);
+        chip8.hardware.quirks.display_wait = true;
+        chip8.hardware.display_sync = false;
+        chip8.config.runner.kind = Chip8RunnerKind::DebugRunner { cycles_to_run: 1 };
+        chip8.config.runner.advance();
+        assert!(!chip8.debug_step());
+        assert!(chip8.runner().can_run());
+        assert_eq!(chip8.hardware.pc, 0x200);
+        chip8.hardware.sync_display();
+        chip8.config.runner.advance();
+        assert!(chip8.debug_step());
+        assert!(!chip8.runner().can_run());
+        assert_eq!(chip8.hardware.pc, 0x202);
+    }
+    #[test]
+    fn the_memory_window_covers_pc_and_i() {
+        // both fit, so they end up in the middle
+        assert_eq!(memory_window_start(0x200, 0x220, 512), 0x120);
+        assert_eq!(memory_window_start(0x220, 0x200, 512), 0x120);
+        // too far apart, so only pc is shown
+        assert_eq!(memory_window_start(0x200, 0xf00, 512), 0x100);
+        // the window stays inside the memory
+        assert_eq!(memory_window_start(0x10, 0x0, 512), 0);
+        assert_eq!(memory_window_start(0xffe, 0xffe, 512), MEMORY_SIZE - 512);
+        assert_eq!(memory_window_start(0x200, 0xffff, MEMORY_SIZE), 0);
+    }
+}
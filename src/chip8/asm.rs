@@ -0,0 +1,388 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use super::rom::{MAX_ROM_SIZE, PROGRAM_START};
+
+/// Syntax of each instruction, the same the debugger shows, and its opcode with all operands 0.
+///
+/// `{x}` and `{y}` are registers, `{n}`, `{nn}` and `{nnn}` numbers of 4, 8 and 12 bits.
+/// Whitespace is optional and the mnemonics are case insensitive.
+const INSTRUCTIONS: &[(&str, u16)] = &[
+    ("clear", 0x00e0),
+    ("return from subroutine", 0x00ee),
+    ("exit", 0x00fd),
+    ("scroll down {n}", 0x00c0),
+    ("scroll right", 0x00fb),
+    ("scroll left", 0x00fc),
+    ("low resolution", 0x00fe),
+    ("high resolution", 0x00ff),
+    ("jmp to {nnn}", 0x1000),
+    ("push subroutine {nnn}", 0x2000),
+    ("skip if r[{x}] == {nn}", 0x3000),
+    ("skip if r[{x}] != {nn}", 0x4000),
+    ("skip if r[{x}] == r[{y}]", 0x5000),
+    ("r[{x}] = {nn}", 0x6000),
+    ("r[{x}] += {nn}", 0x7000),
+    ("r[{x}] = r[{y}]", 0x8000),
+    ("r[{x}] = r[{x}] | r[{y}]", 0x8001),
+    ("r[{x}] = r[{x}] & r[{y}]", 0x8002),
+    ("r[{x}] = r[{x}] ^ r[{y}]", 0x8003),
+    ("r[{x}] = r[{x}] + r[{y}]", 0x8004),
+    ("r[{x}] = r[{x}] - r[{y}]", 0x8005),
+    ("r[{x}] = r[{x}] >> 1", 0x8006),
+    ("r[{x}] = r[{y}] - r[{x}]", 0x8007),
+    ("r[{x}] = r[{x}] << 1", 0x800e),
+    ("skip if r[{x}] != r[{y}]", 0x9000),
+    ("i = {nnn}", 0xa000),
+    ("pc = r[{x}] + {nnn}", 0xb000),
+    ("r[{x}] = rand & {nn}", 0xc000),
+    ("draw at x=r[{x}],y=r[{y}] height {n}", 0xd000),
+    ("skip if r[{x}] pressed", 0xe09e),
+    ("skip if r[{x}] not pressed", 0xe0a1),
+    ("r[{x}] = delay", 0xf007),
+    ("r[{x}] = wait for key", 0xf00a),
+    ("delay = r[{x}]", 0xf015),
+    ("sound = r[{x}]", 0xf018),
+    ("i += r[{x}]", 0xf01e),
+    ("i = r[{x}]th char", 0xf029),
+    ("i = r[{x}]th big char", 0xf030),
+    ("store r[0] to r[{x}] in mem", 0xf055),
+    ("load r[0] to r[{x}] from mem", 0xf065),
+];
+
+/// Error of [`assemble`] with the position it occurred at, both starting at 1.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AsmErrorKind {
+    UnknownInstruction,
+    MissingOperand,
+    InvalidNumber(String),
+    /// Registers go from 0 to 15.
+    InvalidRegister(String),
+    /// The operand doesn't fit into the bits of the instruction.
+    OutOfRange(String),
+    /// Operands sharing bits of the instruction have different values, like `r[{x}]` in `pc = r[{x}] + {nnn}`.
+    ConflictingOperands,
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    /// The program doesn't fit into memory.
+    TooLarge,
+}
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            AsmErrorKind::UnknownInstruction => write!(f, "unknown instruction"),
+            AsmErrorKind::MissingOperand => write!(f, "missing operand"),
+            AsmErrorKind::InvalidNumber(token) => write!(f, "{token} isn't a number"),
+            AsmErrorKind::InvalidRegister(token) => {
+                write!(f, "{token} isn't a register between 0 and 15")
+            }
+            AsmErrorKind::OutOfRange(token) => write!(f, "{token} is too large for the operand"),
+            AsmErrorKind::ConflictingOperands => write!(f, "the operands don't fit together"),
+            AsmErrorKind::UnknownLabel(label) => write!(f, "unknown label {label}"),
+            AsmErrorKind::DuplicateLabel(label) => write!(f, "label {label} is already defined"),
+            AsmErrorKind::TooLarge => write!(f, "the program doesn't fit into memory"),
+        }
+    }
+}
+impl Error for AsmError {}
+
+/// A line of source without its label and comment. Columns start at 1.
+enum Statement<'a> {
+    Empty,
+    /// Bytes of a `db` directive.
+    Data(Vec<(usize, &'a str)>),
+    /// Characters of an instruction without whitespace.
+    Instruction(Vec<(usize, char)>),
+}
+impl Statement<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Empty => 0,
+            Statement::Data(bytes) => bytes.len(),
+            Statement::Instruction(_) => 2,
+        }
+    }
+}
+
+/// Assembles `src` into a rom loaded at [`PROGRAM_START`].
+///
+/// Each line holds an instruction, a `db` directive followed by data bytes, or nothing.
+/// Lines can start with a `label:` and `;` starts a comment.
+/// Numbers are hex like in the debugger, optionally with `0x`, and decimal with a leading `#`.
+/// Registers are written in decimal, `r[0]` to `r[15]`, and labels can be used in place of numbers.
+///
+/// ```text
+/// loop:   i = sprite
+///         draw at x=r[0],y=r[1] height 5
+///         jmp to loop
+/// sprite: db f0 90 90 90 f0
+/// ```
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = PROGRAM_START;
+    for (index, text) in src.lines().enumerate() {
+        let line = index + 1;
+        let code = text.split(';').next().unwrap_or_default();
+        let (label, body_start) = split_label(code);
+        if let Some((column, name)) = label {
+            if labels.insert(name, addr as u16).is_some() {
+                let kind = AsmErrorKind::DuplicateLabel(name.to_string());
+                return Err(AsmError { line, column, kind });
+            }
+        }
+        let statement = parse_statement(&code[body_start..], body_start);
+        addr += statement.len();
+        if addr > PROGRAM_START + MAX_ROM_SIZE {
+            let kind = AsmErrorKind::TooLarge;
+            return Err(AsmError {
+                line,
+                column: 1,
+                kind,
+            });
+        }
+        statements.push((line, statement));
+    }
+    let mut program = Vec::new();
+    for (line, statement) in statements {
+        match statement {
+            Statement::Empty => {}
+            Statement::Data(bytes) => {
+                for (column, token) in bytes {
+                    let byte = operand(token, 8, &labels).map_err(|kind| AsmError {
+                        line,
+                        column,
+                        kind,
+                    })?;
+                    program.push(byte as u8);
+                }
+            }
+            Statement::Instruction(chars) => {
+                let op = encode(&chars, &labels).map_err(|(column, kind)| AsmError {
+                    line,
+                    column,
+                    kind,
+                })?;
+                program.extend(op.to_be_bytes());
+            }
+        }
+    }
+    Ok(program)
+}
+/// Splits a leading `label:` off `code`.
+/// Returns the label with its column and the offset of the rest of the line.
+fn split_label(code: &str) -> (Option<(usize, &str)>, usize) {
+    let start = code.len() - code.trim_start().len();
+    let rest = &code[start..];
+    let len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let name = &rest[..len];
+    if !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && rest[len..].starts_with(':')
+    {
+        return (Some((start + 1, name)), start + len + 1);
+    }
+    (None, 0)
+}
+/// Parses the part of a line behind its label, which starts at byte `offset` of the line.
+fn parse_statement(body: &str, offset: usize) -> Statement<'_> {
+    let words = words(body, offset);
+    match words.first() {
+        None => Statement::Empty,
+        Some((_, first)) if first.eq_ignore_ascii_case("db") => {
+            Statement::Data(words[1..].to_vec())
+        }
+        Some(_) => Statement::Instruction(
+            body.char_indices()
+                .filter(|(_, c)| !c.is_whitespace())
+                .map(|(i, c)| (offset + i + 1, c))
+                .collect(),
+        ),
+    }
+}
+/// Words of `text` separated by whitespace or commas, with their columns.
+fn words(text: &str, offset: usize) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        let separator = c.is_whitespace() || c == ',';
+        match (start, separator) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push((offset + s + 1, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+/// Encodes the instruction made of `chars`.
+///
+/// If no instruction matches, the error of the one that matched the most characters is returned.
+fn encode(
+    chars: &[(usize, char)],
+    labels: &HashMap<&str, u16>,
+) -> Result<u16, (usize, AsmErrorKind)> {
+    let mut best: Option<(usize, AsmErrorKind)> = None;
+    for &(template, base) in INSTRUCTIONS {
+        match match_template(template, base, chars, labels) {
+            Ok(op) => return Ok(op),
+            Err(Some((index, kind))) if best.as_ref().is_none_or(|(best, _)| index > *best) => {
+                best = Some((index, kind));
+            }
+            Err(_) => {}
+        }
+    }
+    let (index, kind) = best.unwrap_or((0, AsmErrorKind::UnknownInstruction));
+    let column = match chars.get(index) {
+        Some((column, _)) => *column,
+        None => chars.last().map_or(1, |(column, _)| column + 1),
+    };
+    Err((column, kind))
+}
+/// Matches `chars` against `template` and encodes the operands into `base`.
+///
+/// Fails with `None` if the characters don't have the syntax of the template,
+/// and with the index of the operand if the syntax matches but an operand is invalid.
+fn match_template(
+    template: &str,
+    base: u16,
+    chars: &[(usize, char)],
+    labels: &HashMap<&str, u16>,
+) -> Result<u16, Option<(usize, AsmErrorKind)>> {
+    let mut op = base;
+    // bits of the opcode set by the operands so far
+    let mut filled = 0;
+    let mut pos = 0;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if c != '{' {
+            rest = &rest[c.len_utf8()..];
+            match chars.get(pos) {
+                _ if c.is_whitespace() => {}
+                Some((_, actual)) if actual.eq_ignore_ascii_case(&c) => pos += 1,
+                _ => return Err(None),
+            }
+            continue;
+        }
+        let (name, after) = rest[1..].split_once('}').expect("placeholders are closed");
+        rest = after;
+        let len = chars[pos..]
+            .iter()
+            .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '_' || *c == '#')
+            .count();
+        let token: String = chars[pos..pos + len].iter().map(|(_, c)| c).collect();
+        let (shift, bits) = match name {
+            "x" => (8, 4),
+            "y" => (4, 4),
+            "n" => (0, 4),
+            "nn" => (0, 8),
+            "nnn" => (0, 12),
+            _ => unreachable!("unknown placeholder {name}"),
+        };
+        let value = match name {
+            "x" | "y" => register(&token),
+            _ => operand(&token, bits, labels),
+        }
+        .map_err(|kind| Some((pos, kind)))?;
+        let mask = ((1 << bits) - 1) << shift;
+        let value = value << shift;
+        if (op ^ value) & filled & mask != 0 {
+            return Err(Some((pos, AsmErrorKind::ConflictingOperands)));
+        }
+        op |= value;
+        filled |= mask;
+        pos += len;
+    }
+    if pos == chars.len() {
+        Ok(op)
+    } else {
+        Err(None)
+    }
+}
+fn register(token: &str) -> Result<u16, AsmErrorKind> {
+    match token.parse() {
+        Ok(register) if register < 16 => Ok(register),
+        _ if token.is_empty() => Err(AsmErrorKind::MissingOperand),
+        _ => Err(AsmErrorKind::InvalidRegister(token.to_string())),
+    }
+}
+/// Value of a number or label operand that has to fit into `bits`.
+fn operand(token: &str, bits: u32, labels: &HashMap<&str, u16>) -> Result<u16, AsmErrorKind> {
+    let value = match (labels.get(token), parse_number(token)) {
+        (Some(&addr), _) => addr as u32,
+        (None, Some(value)) => value,
+        _ if token.is_empty() => return Err(AsmErrorKind::MissingOperand),
+        _ if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+            return Err(AsmErrorKind::UnknownLabel(token.to_string()))
+        }
+        _ => return Err(AsmErrorKind::InvalidNumber(token.to_string())),
+    };
+    if value >= 1 << bits {
+        return Err(AsmErrorKind::OutOfRange(token.to_string()));
+    }
+    Ok(value as u16)
+}
+/// Parses hex numbers with an optional `0x` and decimal numbers starting with `#`.
+fn parse_number(token: &str) -> Option<u32> {
+    match token.strip_prefix('#') {
+        Some(decimal) => decimal.parse().ok(),
+        None => u32::from_str_radix(token.strip_prefix("0x").unwrap_or(token), 16).ok(),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_labels_and_data() {
+        let src = "
+start:  r[0] = #10 ; decimal
+        i = sprite
+        draw at x=r[0],y=r[1] height 5
+        jmp to start
+sprite: db f0, 0x90 #255";
+        let program = assemble(src).unwrap();
+        assert_eq!(
+            program,
+            [0x60, 0x0a, 0xa2, 0x08, 0xd0, 0x15, 0x12, 0x00, 0xf0, 0x90, 0xff]
+        );
+    }
+    #[test]
+    fn errors_point_at_the_operand() {
+        let error = |src| assemble(src).map_err(|e| (e.line, e.column, e.kind));
+        assert_eq!(
+            error("clear\n  jmp to nowhere"),
+            Err((2, 10, AsmErrorKind::UnknownLabel("nowhere".into())))
+        );
+        assert_eq!(
+            error("r[16] = 1"),
+            Err((1, 3, AsmErrorKind::InvalidRegister("16".into())))
+        );
+        assert_eq!(
+            error("r[0] = 100"),
+            Err((1, 8, AsmErrorKind::OutOfRange("100".into())))
+        );
+        assert_eq!(
+            error("pc = r[1] + 200"),
+            Err((1, 13, AsmErrorKind::ConflictingOperands))
+        );
+        assert_eq!(
+            error("x: clear\nx: exit"),
+            Err((2, 1, AsmErrorKind::DuplicateLabel("x".into())))
+        );
+        assert_eq!(
+            error("bogus"),
+            Err((1, 1, AsmErrorKind::UnknownInstruction))
+        );
+    }
+}
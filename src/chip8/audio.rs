@@ -0,0 +1,101 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BuildStreamError, DefaultStreamConfigError, PlayStreamError, Stream,
+};
+
+/// Pitch of the buzzer.
+const TONE_HZ: f32 = 440.;
+/// Amplitude of the tone. Kept low since square waves are loud.
+const VOLUME: f32 = 0.1;
+
+#[derive(Debug)]
+pub enum AudioError {
+    NoOutputDevice,
+    ConfigError(DefaultStreamConfigError),
+    BuildError(BuildStreamError),
+    PlayError(PlayStreamError),
+}
+impl Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::NoOutputDevice => write!(f, "no audio output device found"),
+            AudioError::ConfigError(e) => e.fmt(f),
+            AudioError::BuildError(e) => e.fmt(f),
+            AudioError::PlayError(e) => e.fmt(f),
+        }
+    }
+}
+impl Error for AudioError {}
+impl From<DefaultStreamConfigError> for AudioError {
+    fn from(value: DefaultStreamConfigError) -> Self {
+        AudioError::ConfigError(value)
+    }
+}
+impl From<BuildStreamError> for AudioError {
+    fn from(value: BuildStreamError) -> Self {
+        AudioError::BuildError(value)
+    }
+}
+impl From<PlayStreamError> for AudioError {
+    fn from(value: PlayStreamError) -> Self {
+        AudioError::PlayError(value)
+    }
+}
+
+/// The buzzer of the chip8, playing a square wave while it's on.
+///
+/// The output stream keeps running silently while the buzzer is off,
+/// so the tone starts and stops with the next audio buffer. Dropping the beeper closes the stream.
+pub struct Beeper {
+    _stream: Stream,
+    beeping: Arc<AtomicBool>,
+}
+impl Beeper {
+    /// Opens a stream on the default output device.
+    pub fn new() -> Result<Beeper, AudioError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(AudioError::NoOutputDevice)?;
+        let config = device.default_output_config()?.config();
+        let channels = config.channels as usize;
+        let step = TONE_HZ / config.sample_rate.0 as f32;
+        let beeping = Arc::new(AtomicBool::new(false));
+        let stream_beeping = Arc::clone(&beeping);
+        // position within the current period of the tone, from 0 to 1
+        let mut phase = 0.;
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _| {
+                let on = stream_beeping.load(Ordering::Relaxed);
+                for frame in data.chunks_mut(channels) {
+                    let sample = match (on, phase < 0.5) {
+                        (false, _) => 0.,
+                        (true, true) => VOLUME,
+                        (true, false) => -VOLUME,
+                    };
+                    frame.fill(sample);
+                    phase = (phase + step) % 1.;
+                }
+            },
+            |e| eprintln!("audio stream failed with {e}"),
+            None,
+        )?;
+        stream.play()?;
+        Ok(Beeper {
+            _stream: stream,
+            beeping,
+        })
+    }
+    pub fn set_beeping(&self, beeping: bool) {
+        self.beeping.store(beeping, Ordering::Relaxed);
+    }
+}
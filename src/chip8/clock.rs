@@ -0,0 +1,131 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Source of time for the emulator loop.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+    /// Sleeps for whatever is left of `frame_time` since `frame_start`.
+    fn sleep_until_frame_end(&mut self, frame_start: Instant, frame_time: Duration) {
+        let elapsed = self.now().saturating_duration_since(frame_start);
+        self.sleep(frame_time.saturating_sub(elapsed));
+    }
+}
+/// Rate the delay and sound timers count down at.
+pub const TIMER_HZ: u32 = 60;
+/// Time between two timer ticks.
+pub const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+/// Instructions executed per second by default.
+pub const DEFAULT_CPU_HZ: u32 = 700;
+
+/// Schedules the timer ticks by time, so the timers run at [`TIMER_HZ`] no matter how fast instructions execute.
+pub struct TimerSchedule {
+    next_tick: Instant,
+    /// Speed of the last call to [`TimerSchedule::due_ticks`].
+    speed: f32,
+}
+impl TimerSchedule {
+    pub fn new(now: Instant) -> TimerSchedule {
+        TimerSchedule {
+            next_tick: now + TIMER_PERIOD,
+            speed: 1.,
+        }
+    }
+    /// Returns how many timer ticks became due up to `now`, with the timers running `speed` times as fast.
+    pub fn due_ticks(&mut self, now: Instant, speed: f32) -> u32 {
+        let period = TIMER_PERIOD.div_f32(speed);
+        if speed != self.speed {
+            // the pending tick moves to one new period after the last tick
+            self.next_tick = self.next_tick - TIMER_PERIOD.div_f32(self.speed) + period;
+            self.speed = speed;
+        }
+        let mut ticks = 0;
+        while self.next_tick <= now {
+            self.next_tick += period;
+            ticks += 1;
+        }
+        ticks
+    }
+}
+/// The wall clock.
+#[derive(Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+/// A clock that only advances when told to, so tests can run many frames instantly.
+#[cfg(test)]
+pub(crate) struct ManualClock {
+    start: Instant,
+    elapsed: Duration,
+}
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+        }
+    }
+    pub fn advance(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed
+    }
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleeps_until_frame_end() {
+        let mut clock = ManualClock::new();
+        let frame_time = Duration::from_millis(16);
+        let start = clock.now();
+        clock.advance(Duration::from_millis(10));
+        clock.sleep_until_frame_end(start, frame_time);
+        assert_eq!(clock.now() - start, frame_time);
+    }
+    #[test]
+    fn late_frames_dont_sleep() {
+        let mut clock = ManualClock::new();
+        let start = clock.now();
+        clock.advance(Duration::from_millis(20));
+        clock.sleep_until_frame_end(start, Duration::from_millis(16));
+        assert_eq!(clock.now() - start, Duration::from_millis(20));
+    }
+    #[test]
+    fn fast_timers_tick_more_often() {
+        let mut clock = ManualClock::new();
+        let mut timers = TimerSchedule::new(clock.now());
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(timers.due_ticks(clock.now(), 1.), 30);
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(timers.due_ticks(clock.now(), 4.), 120);
+    }
+    #[test]
+    fn runs_many_frames_instantly() {
+        let mut clock = ManualClock::new();
+        let frame_time = Duration::from_secs_f32(1. / 60.);
+        let start = clock.now();
+        for _ in 0..6000 {
+            let frame_start = clock.now();
+            clock.sleep_until_frame_end(frame_start, frame_time);
+        }
+        assert_eq!(clock.now() - start, frame_time * 6000);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+/// Counts how often each kind of instruction was executed,
+/// showing which opcodes and quirks a program depends on.
+#[derive(Default)]
+pub struct Coverage {
+    counts: BTreeMap<&'static str, u64>,
+}
+impl Coverage {
+    pub fn record(&mut self, instr: u16) {
+        if let Some(pattern) = opcode_pattern(instr) {
+            *self.counts.entry(pattern).or_default() += 1;
+        }
+    }
+    /// Formats the executed opcodes as a table of opcode and count.
+    pub fn report(&self) -> String {
+        let mut report = String::from("opcode  count\n");
+        for (pattern, count) in &self.counts {
+            report += &format!("{pattern:<8}{count}\n");
+        }
+        report
+    }
+}
+/// Returns the opcode pattern `instr` is decoded as, following the match arms of `Hardware::decode`.
+pub(super) fn opcode_pattern(instr: u16) -> Option<&'static str> {
+    let op = (instr & 0xF000) >> 12;
+    let x = (instr & 0x0F00) >> 8;
+    let y = (instr & 0x00F0) >> 4;
+    let n = instr & 0x000F;
+    let pattern = match (op, x, y, n) {
+        (0x0, 0x0, 0xe, 0x0) => "00E0",
+        (0x0, 0x0, 0xf, 0xd) => "00FD",
+        (0x0, 0x0, 0xc, _) => "00CN",
+        (0x0, 0x0, 0xf, 0xb) => "00FB",
+        (0x0, 0x0, 0xf, 0xc) => "00FC",
+        (0x0, 0x0, 0xf, 0xe) => "00FE",
+        (0x0, 0x0, 0xf, 0xf) => "00FF",
+        (0x0, 0x0, 0xe, 0xe) => "00EE",
+        (0x1, _, _, _) => "1NNN",
+        (0x2, _, _, _) => "2NNN",
+        (0x3, _, _, _) => "3XNN",
+        (0x4, _, _, _) => "4XNN",
+        (0x5, _, _, 0) => "5XY0",
+        (0x6, _, _, _) => "6XNN",
+        (0x7, _, _, _) => "7XNN",
+        (0x8, _, _, 0) => "8XY0",
+        (0x8, _, _, 1) => "8XY1",
+        (0x8, _, _, 2) => "8XY2",
+        (0x8, _, _, 3) => "8XY3",
+        (0x8, _, _, 4) => "8XY4",
+        (0x8, _, _, 5) => "8XY5",
+        (0x8, _, _, 6) => "8XY6",
+        (0x8, _, _, 7) => "8XY7",
+        (0x8, _, _, 0xe) => "8XYE",
+        (0x9, _, _, 0) => "9XY0",
+        (0xa, _, _, _) => "ANNN",
+        (0xb, _, _, _) => "BNNN",
+        (0xc, _, _, _) => "CXNN",
+        (0xd, _, _, 0) => "DXY0",
+        (0xd, _, _, _) => "DXYN",
+        (0xe, _, 9, 0xe) => "EX9E",
+        (0xe, _, 0xa, 1) => "EXA1",
+        (0xf, _, 0, 7) => "FX07",
+        (0xf, _, 1, 5) => "FX15",
+        (0xf, _, 1, 8) => "FX18",
+        (0xf, _, 1, 0xe) => "FX1E",
+        (0xf, _, 0, 0xa) => "FX0A",
+        (0xf, _, 2, 9) => "FX29",
+        (0xf, _, 3, 0) => "FX30",
+        (0xf, _, 3, 3) => "FX33",
+        (0xf, _, 5, 5) => "FX55",
+        (0xf, _, 6, 5) => "FX65",
+        _ => return None,
+    };
+    Some(pattern)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::hardware::{DecodeError, Generation, Hardware, NO_KEYS};
+
+    #[test]
+    fn every_decoded_opcode_has_a_pattern() {
+        for instr in 0..=u16::MAX {
+            let mut hardware = Hardware::default();
+            hardware.set_generation(Generation::Super);
+            if !matches!(
+                hardware.decode(instr, &NO_KEYS),
+                Err(DecodeError::UnknownOpcode(_))
+            ) {
+                assert!(
+                    opcode_pattern(instr).is_some(),
+                    "{instr:04x} has no pattern"
+                );
+            }
+        }
+    }
+}
@@ -13,6 +13,12 @@ pub fn map_op(instr: u16) -> String {
         (0x0, 0x0, 0xe, 0x0) => "clear".into(),
 
         (0x0, 0x0, 0xe, 0xe) => "return from subroutine".into(),
+        (0x0, 0x0, 0xf, 0xd) => "exit".into(),
+        (0x0, 0x0, 0xc, _) => format!("scroll down {n:x}"),
+        (0x0, 0x0, 0xf, 0xb) => "scroll right".into(),
+        (0x0, 0x0, 0xf, 0xc) => "scroll left".into(),
+        (0x0, 0x0, 0xf, 0xe) => "low resolution".into(),
+        (0x0, 0x0, 0xf, 0xf) => "high resolution".into(),
 
         (0x1, _, _, _) => format!("jmp to {nnn:x}"),
         (0x2, _, _, _) => format!("push subroutine {nnn:x}"),
@@ -31,21 +37,39 @@ pub fn map_op(instr: u16) -> String {
         (0x8, _, _, 7) => format!("r[{x}] = r[{y}] - r[{x}]"),
         (0x8, _, _, 0xe) => format!("r[{x}] = r[{x}] << 1"),
         (0x9, _, _, 0) => format!("skip if r[{x}] != r[{y}]"),
-        (0xa, _, _, _) => format!("i = {nnn}"),
+        (0xa, _, _, _) => format!("i = {nnn:x}"),
         (0xb, _, _, _) => format!("pc = r[{x}] + {nnn:x}"),
-        (0xc, _, _, _) => format!("rand & {nn:x}"),
-        (0xd, _, _, _) => format!("draw at x=r[{x}],y=r[{y}]"),
-        (0xe, _, 9, 0xe) => format!("skip if r[{x}]) pressed"),
-        (0xe, _, 0xa, 1) => format!("skip if r[{x}]) not pressed"),
+        (0xc, _, _, _) => format!("r[{x}] = rand & {nn:x}"),
+        (0xd, _, _, _) => format!("draw at x=r[{x}],y=r[{y}] height {n:x}"),
+        (0xe, _, 9, 0xe) => format!("skip if r[{x}] pressed"),
+        (0xe, _, 0xa, 1) => format!("skip if r[{x}] not pressed"),
         (0xf, _, 0, 7) => format!("r[{x}] = delay"),
         (0xf, _, 1, 5) => format!("delay = r[{x}]"),
         (0xf, _, 1, 8) => format!("sound = r[{x}]"),
         (0xf, _, 1, 0xe) => format!("i += r[{x}]"),
-        (0xf, _, 0, 0xa) => "wait for any keypres".into(),
+        (0xf, _, 0, 0xa) => format!("r[{x}] = wait for key"),
 
         (0xf, _, 2, 9) => format!("i = r[{x}]th CHAR"),
-        (0xf, _, 5, 5) => "store regs in mem".into(),
-        (0xf, _, 6, 5) => "load regs from mem".into(),
+        (0xf, _, 3, 0) => format!("i = r[{x}]th big CHAR"),
+        (0xf, _, 5, 5) => format!("store r[0] to r[{x}] in mem"),
+        (0xf, _, 6, 5) => format!("load r[0] to r[{x}] from mem"),
         _ => "".into(),
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::asm::assemble;
+
+    #[test]
+    fn mnemonics_assemble_back() {
+        for op in 0..=u16::MAX {
+            let text = map_op(op);
+            if text.is_empty() {
+                continue;
+            }
+            let program = assemble(&text).unwrap_or_else(|e| panic!("{text}: {e}"));
+            assert_eq!(map_op(u16::from_be_bytes([program[0], program[1]])), text);
+        }
+    }
+}
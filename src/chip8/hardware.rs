@@ -1,16 +1,11 @@
-use std::sync::{Arc, RwLock};
+use std::{error::Error, fmt::Display};
 
-use pixels::Pixels;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use winit::event_loop::EventLoopProxy;
 
-use crate::{
-    chip8::screen::{SCREEN_HEIGHT, SCREEN_WIDTH},
-    display_bus::AppEvents,
-    io::InputState,
-};
-
-use super::screen;
+use super::rom::{RomError, MAX_ROM_SIZE, PROGRAM_START};
+use super::save_state::SaveState;
+use super::screen::{DisplayCommand, Framebuffer, Resolution, Scroll};
 
 const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -30,28 +25,169 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+/// Address of the 10 byte tall digits of Super-CHIP, right behind [`FONT`].
+const BIG_FONT_START: usize = FONT.len();
+const BIG_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+/// Bytes of RAM.
+pub const MEMORY_SIZE: usize = 4096;
+/// Keypad the program reads, one bit per pressed key.
+pub trait Chip8Input {
+    /// Returns the pressed keys, `None` if they couldn't be read.
+    fn sample(&self) -> Option<u16>;
+    /// Returns whether the chip8 `key` is pressed, `None` if the keys couldn't be read.
+    /// Only uses up quick taps of `key`, so checks of other keys don't drop them.
+    fn check_key(&self, key: u8) -> Option<bool> {
+        self.sample().map(|keys| is_pressed(keys, key))
+    }
+    /// Like [`Chip8Input::sample`], but gives up instead of waiting for the keys.
+    fn try_sample(&self) -> Option<u16> {
+        self.sample()
+    }
+}
+/// Input without any held keys, for running without a keyboard.
+pub const NO_KEYS: u16 = 0;
+/// Keys that are always held.
+impl Chip8Input for u16 {
+    fn sample(&self) -> Option<u16> {
+        Some(*self)
+    }
+}
+/// Returns whether the chip8 `key` is set in `keys`. Only the low nibble of `key` is used.
+pub const fn is_pressed(keys: u16, key: u8) -> bool {
+    keys & (1 << (key & 0xF)) != 0
+}
 pub struct Hardware {
-    pub memory: [u8; 4096],         // 4kb of RAM
+    pub memory: [u8; MEMORY_SIZE],  // 4kb of RAM
     stack: [u16; 32], // The stack offers a max depth of 32 with 2 bytes per stack frame
-    stack_frame: i8,  // Current stack frame. Starts at -1 and is set to 0 on first use
-    pub(crate) i: u16, // Represents the 16-bit Index register
+    stack_frame: usize, // Number of stack frames in use, the next return address is stored at this index
+    pub(crate) i: u16,  // Represents the 16-bit Index register
     pub(crate) registers: [u8; 16], // Represents the 16 registers
     pub(crate) pc: u16, // Program counter, set it to the initial memory offset
-    delay_timer: u8,  // Represents the delay timer that's decremented at 60hz if > 0
-    sound_timer: u8,  // The sound timer that's decremented at 60hz and plays a beep if > 0
+    delay_timer: u8,    // Represents the delay timer that's decremented at 60hz if > 0
+    sound_timer: u8,    // The sound timer that's decremented at 60hz and plays a beep if > 0
     generation: Generation,
+    pub(crate) quirks: Quirks,
     pub(crate) display_sync: bool,
+    stalled: bool, // Set if the last instruction couldn't execute and has to be retried
+    halted: bool,  // Set once the program exited with 00FD
+    framebuffer: Framebuffer, // Logical display used for collisions, independent of what the app shows
+    waiting_key: Option<u8>,  // Key pressed during FX0A, which continues once it's released
+    rng: fastrand::Rng,       // Source of the random numbers of CXNN, seeded for reproducible runs
 }
-#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
 pub enum Generation {
     Cosmac,
     #[default]
     Super,
 }
+/// Behaviors that differ between chip8 interpreters.
+/// Each generation has its own defaults, but every quirk can be changed individually.
+/// Quirks missing when deserializing keep the defaults, so settings from before a quirk existed stay readable.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE set VX to VY before shifting instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// BXNN jumps to VX + XNN instead of BNNN jumping to V0 + NNN.
+    pub jump_uses_vx: bool,
+    /// Sprites drawn past the edges of the screen are clipped instead of wrapping around.
+    pub clip_sprites: bool,
+    /// FX55 and FX65 leave i behind the last stored or loaded register instead of unchanged.
+    pub load_store_increments_i: bool,
+    /// DXYN waits for the display to show the previous draw, limiting draws to one per frame.
+    pub display_wait: bool,
+}
+impl Quirks {
+    pub const fn for_generation(generation: Generation) -> Quirks {
+        match generation {
+            Generation::Cosmac => Quirks {
+                shift_uses_vy: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+                load_store_increments_i: true,
+                display_wait: true,
+            },
+            Generation::Super => Quirks {
+                shift_uses_vy: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                load_store_increments_i: false,
+                display_wait: false,
+            },
+        }
+    }
+}
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::for_generation(Generation::default())
+    }
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeError {
+    UnknownOpcode(u16),
+    /// 00EE without a subroutine to return from.
+    StackUnderflow,
+    /// 2NNN with all stack frames in use.
+    StackOverflow,
+    /// The instruction accessed memory past the end starting at the address.
+    MemoryOutOfBounds(u16),
+}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(instr) => write!(f, "unknown op code: {instr:x}"),
+            DecodeError::StackUnderflow => {
+                write!(f, "returned from a subroutine with an empty stack")
+            }
+            DecodeError::StackOverflow => write!(f, "called a subroutine with a full stack"),
+            DecodeError::MemoryOutOfBounds(addr) => {
+                write!(f, "accessed memory past the end starting at {addr:x}")
+            }
+        }
+    }
+}
+impl Error for DecodeError {}
+/// An instruction that couldn't be executed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Chip8Error {
+    pub error: DecodeError,
+    /// Address of the instruction.
+    pub pc: u16,
+}
+impl Display for Chip8Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {:04x}", self.error, self.pc)
+    }
+}
+impl Error for Chip8Error {}
+/// What a step of the hardware did, besides changing registers and memory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StepOutcome {
+    /// The instruction didn't change the display.
+    Executed,
+    /// The instruction changed the display, which is already applied to the framebuffer.
+    Display(DisplayCommand),
+    /// A draw waits for [`Hardware::sync_display`] and runs again on the next step.
+    Stalled,
+    /// The program exited, nothing is executed anymore.
+    Halted,
+}
 impl Default for Hardware {
     fn default() -> Self {
-        let mut memory = [0; 4096];
+        let mut memory = [0; MEMORY_SIZE];
         memory[..FONT.len()].copy_from_slice(&FONT[..]);
+        memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT.len()].copy_from_slice(&BIG_FONT[..]);
         Hardware {
             memory,
             stack: [0; 32],
@@ -62,7 +198,13 @@ impl Default for Hardware {
             delay_timer: 0,
             sound_timer: 0,
             generation: Generation::default(),
+            quirks: Quirks::default(),
             display_sync: true,
+            stalled: false,
+            halted: false,
+            framebuffer: Framebuffer::default(),
+            waiting_key: None,
+            rng: fastrand::Rng::new(),
         }
     }
 }
@@ -70,16 +212,48 @@ impl Hardware {
     pub fn set_generation(&mut self, generation: Generation) {
         self.generation = generation;
     }
-    pub fn load_program(&mut self, program: &[u8]) {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+    /// Makes the random numbers of CXNN the same on every run with `seed`.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+    /// Copies `program` to [`PROGRAM_START`], failing if it doesn't fit into memory.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), RomError> {
         let len = program.len();
-        self.memory[0x200..0x200 + len].copy_from_slice(program);
+        if len > MAX_ROM_SIZE {
+            return Err(RomError::TooLarge(len));
+        }
+        self.memory[PROGRAM_START..PROGRAM_START + len].copy_from_slice(program);
+        Ok(())
     }
     pub fn fetch(&mut self) -> u16 {
-        let instr = ((self.memory[self.pc()] as u16) << 8) | self.memory[self.pc() + 1] as u16;
-        // convert the 2-bytes into a u16.
-        self.pc += 2;
+        let instr = self.peek_instr();
+        self.pc = self.pc.wrapping_add(2);
         instr
     }
+    /// Returns the instruction at the pc without advancing it.
+    /// Addresses past the end of memory wrap around.
+    pub fn peek_instr(&self) -> u16 {
+        // convert the 2-bytes into a u16.
+        ((self.memory[self.pc() % self.memory.len()] as u16) << 8)
+            | self.memory[(self.pc() + 1) % self.memory.len()] as u16
+    }
+    /// Returns `len` bytes of memory starting at `start`.
+    fn memory(&self, start: u16, len: usize) -> Result<&[u8], DecodeError> {
+        let start_index = start as usize;
+        self.memory
+            .get(start_index..start_index + len)
+            .ok_or(DecodeError::MemoryOutOfBounds(start))
+    }
+    /// Returns `len` bytes of memory starting at `start` for writing.
+    fn memory_mut(&mut self, start: u16, len: usize) -> Result<&mut [u8], DecodeError> {
+        let start_index = start as usize;
+        self.memory
+            .get_mut(start_index..start_index + len)
+            .ok_or(DecodeError::MemoryOutOfBounds(start))
+    }
     pub fn set_flag(&mut self, is_set: bool) {
         if is_set {
             self.registers[15] = 1;
@@ -87,13 +261,49 @@ impl Hardware {
             self.registers[15] = 0;
         }
     }
+    /// Fetches and executes the instruction at the pc.
+    ///
+    /// ```
+    /// use chip8::chip8::hardware::{Hardware, StepOutcome, NO_KEYS};
+    /// use chip8::chip8::screen::DisplayCommand;
+    ///
+    /// let mut hardware = Hardware::default();
+    /// // clear the screen and set va to 42
+    /// hardware.load_program(&[0x00, 0xe0, 0x6a, 0x2a]).unwrap();
+    /// assert_eq!(
+    ///     hardware.step(&NO_KEYS),
+    ///     Ok(StepOutcome::Display(DisplayCommand::Clear))
+    /// );
+    /// assert_eq!(hardware.step(&NO_KEYS), Ok(StepOutcome::Executed));
+    /// assert_eq!(hardware.registers()[0xa], 42);
+    /// ```
+    pub fn step(&mut self, input: &impl Chip8Input) -> Result<StepOutcome, Chip8Error> {
+        if self.halted {
+            return Ok(StepOutcome::Halted);
+        }
+        let pc = self.pc;
+        let instr = self.fetch();
+        let mut commands = self
+            .decode(instr, input)
+            .map_err(|error| Chip8Error { error, pc })?;
+        if self.stalled {
+            return Ok(StepOutcome::Stalled);
+        }
+        if self.halted {
+            return Ok(StepOutcome::Halted);
+        }
+        // instructions change the display at most once
+        Ok(match commands.pop() {
+            Some(command) => StepOutcome::Display(command),
+            None => StepOutcome::Executed,
+        })
+    }
+    /// Executes `instr` and returns the changes of the display, which are already applied to the framebuffer.
     pub fn decode(
         &mut self,
         instr: u16,
-        bus: &EventLoopProxy<AppEvents>,
-        pixel_buffer: &Arc<RwLock<Pixels>>,
-        input: &Arc<RwLock<InputState>>,
-    ) {
+        input: &impl Chip8Input,
+    ) -> Result<Vec<DisplayCommand>, DecodeError> {
         let b0 = (instr & 0xFF00) >> 8u8; // To get first byte, & the 8 leftmost bits which removes the 8 rightmost, then shift by 8 to the right to make the u8 conversion contain the bits originally on the left.
                                           // println!("instr: {instr:x}, pc: {pc:x}", pc = self.pc);
         let b1 = (instr & 0x00FF) as u8; // To get the second byte, just & the 8 rightmost bits, which removes the leftmost bits. The remaining bits are already at the rightmost position so no need to shift before converting to u8.
@@ -104,22 +314,37 @@ impl Hardware {
         let n = b1 & 0x0F; // fourth nibble, 4 bit number
         let nn = b1; // NN = second byte
         let nnn = instr & 0x0FFF; // NNN = second, third and fourth nibbles, obtained by ANDing by b00001111 11111111 masking away the first nibble.
+        let super_chip = matches!(self.generation, Generation::Super);
+        self.stalled = false;
+        let mut commands = Vec::new();
         match (op, x, y, n) {
             // Clear screen
-            (0x0, 0x0, 0xe, 0x0) => bus.send_event(AppEvents::ClearScreen).unwrap(),
-            // Return from subroutine
-            (0x0, 0x0, 0xe, 0xe) => {
-                self.stack_frame -= 1;
-                self.pc = self.stack[self.stack_frame as usize];
+            (0x0, 0x0, 0xe, 0x0) => {
+                self.framebuffer.clear();
+                commands.push(DisplayCommand::Clear);
             }
+            // Exit the interpreter (Super-CHIP)
+            (0x0, 0x0, 0xf, 0xd) if super_chip => self.halted = true,
+            // Scroll down N rows (Super-CHIP)
+            (0x0, 0x0, 0xc, rows) if super_chip => commands.push(self.scroll(Scroll::Down(rows))),
+            // Scroll right 4 columns (Super-CHIP)
+            (0x0, 0x0, 0xf, 0xb) if super_chip => commands.push(self.scroll(Scroll::Right(4))),
+            // Scroll left 4 columns (Super-CHIP)
+            (0x0, 0x0, 0xf, 0xc) if super_chip => commands.push(self.scroll(Scroll::Left(4))),
+            // Low resolution (Super-CHIP)
+            (0x0, 0x0, 0xf, 0xe) if super_chip => {
+                commands.push(self.set_resolution(Resolution::Low))
+            }
+            // High resolution (Super-CHIP)
+            (0x0, 0x0, 0xf, 0xf) if super_chip => {
+                commands.push(self.set_resolution(Resolution::High))
+            }
+            // Return from subroutine
+            (0x0, 0x0, 0xe, 0xe) => self.return_from_subroutine()?,
             // Jump
             (0x1, _, _, _) => self.pc = nnn,
             // Push subroutine
-            (0x2, _, _, _) => {
-                self.stack[self.stack_frame as usize] = self.pc;
-                self.stack_frame += 1;
-                self.pc = nnn;
-            }
+            (0x2, _, _, _) => self.call_subroutine(nnn)?,
             (0x3, _, _, _) => {
                 if self.registers[x] == nn {
                     self.pc += 2;
@@ -166,30 +391,13 @@ impl Hardware {
                 self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
                 self.set_flag(flag);
             }
-            (0x8, _, _, 6) => {
-                match self.generation {
-                    Generation::Cosmac => {
-                        self.registers[x] = self.registers[y];
-                    }
-                    Generation::Super => {}
-                }
-                let flag = self.registers[x] & 1 == 1;
-                self.registers[x] >>= 1;
-                self.set_flag(flag);
-            }
+            (0x8, _, _, 6) => self.shift_right(x, y),
             (0x8, _, _, 7) => {
                 let flag = self.registers[x] <= self.registers[y];
                 self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
                 self.set_flag(flag);
             }
-            (0x8, _, _, 0xe) => {
-                if matches!(self.generation, Generation::Cosmac) {
-                    self.registers[x] = self.registers[y];
-                }
-                let flag = (self.registers[x] >> 7) == 1;
-                self.registers[x] <<= 1;
-                self.set_flag(flag);
-            }
+            (0x8, _, _, 0xe) => self.shift_left(x, y),
 
             (0x9, _, _, 0) => {
                 if self.registers[x] != self.registers[y] {
@@ -200,77 +408,57 @@ impl Hardware {
             (0xa, _, _, _) => {
                 self.i = nnn;
             }
-            (0xb, _, _, _) => match self.generation {
-                Generation::Cosmac => self.pc = self.registers[0] as u16 + nnn,
-                Generation::Super => {
+            (0xb, _, _, _) => {
+                if self.quirks.jump_uses_vx {
                     self.pc = self.registers[x] as u16 + nnn;
+                } else {
+                    self.pc = self.registers[0] as u16 + nnn;
                 }
-            },
+            }
             (0xc, _, _, _) => {
-                let number = fastrand::u8(..);
+                let number = self.rng.u8(..);
                 self.registers[x] = number & nn;
             }
             // display/draw
             (0xd, reg_x, reg_y, sprite_height) => {
-                if !self.display_sync {
+                if self.quirks.display_wait && !self.display_sync {
                     self.pc -= 2;
-                    return;
+                    self.stalled = true;
+                    return Ok(commands);
                 }
+                let sprite: Vec<u16> = if sprite_height == 0 && super_chip {
+                    // 16×16 sprite (Super-CHIP), two bytes per row
+                    self.memory(self.i, 32)?
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                        .collect()
+                } else {
+                    self.memory(self.i, sprite_height as usize)?
+                        .iter()
+                        .map(|&byte| (byte as u16) << 8)
+                        .collect()
+                };
                 self.display_sync = false;
-                let x = self.registers[reg_x] % SCREEN_WIDTH as u8;
-                let y = self.registers[reg_y] % SCREEN_HEIGHT as u8;
-                // set flag register to 0
-                let i = self.i;
-                let mut sprite: [u8; 16] = [0; 16];
-                for n in 0..sprite_height {
-                    let row_start = i + n as u16;
-                    let row = self.memory[row_start as usize];
-                    sprite[n as usize] = row;
-                }
-                let mut flip = false;
-                if let Ok(pixel_buffer) = pixel_buffer.read() {
-                    bus.send_event(AppEvents::DrawSprite { sprite, x, y })
-                        .unwrap();
-                    for n in 0..16 {
-                        let row_i = y as usize + n as usize;
-                        let sprite_row = sprite[n as usize];
-                        if sprite_row == 0 {
-                            continue;
-                        }
-                        let screen_row = screen::pixel_row(&pixel_buffer, row_i);
-                        flip = screen_row
-                            .chunks_exact(4)
-                            .skip(x as usize)
-                            .take(8)
-                            .enumerate()
-                            .filter(|(i, _pixel)| sprite_row & (1 << (7 - i)) != 0)
-                            .any(|(_i, c)| *c != [0, 0, 0, 0]);
-                        if flip {
-                            self.set_flag(true);
-                            break;
-                        }
-                    }
-                    if !flip {
-                        self.set_flag(false);
-                    }
-                }
+                let resolution = self.framebuffer.resolution();
+                let x = (self.registers[reg_x] as usize % resolution.width()) as u8;
+                let y = (self.registers[reg_y] as usize % resolution.height()) as u8;
+                let wrap = !self.quirks.clip_sprites;
+                let flip = self
+                    .framebuffer
+                    .draw_sprite(x as usize, y as usize, &sprite, wrap);
+                commands.push(DisplayCommand::Draw { sprite, x, y, wrap });
+                self.set_flag(flip);
             }
             (0xe, _, 9, 0xe) => {
                 let key = self.registers[x];
-                if let Ok(input) = input.read() {
-                    let pressed_keys = input.pressed();
-                    if pressed_keys & (1 << key) != 0 {
-                        self.pc += 2;
-                    }
+                if input.check_key(key) == Some(true) {
+                    self.pc += 2;
                 }
             }
             (0xe, _, 0xa, 1) => {
                 let key = self.registers[x];
-                if let Ok(input) = input.read() {
-                    let pressed_keys = input.pressed();
-                    if pressed_keys & (1 << key) == 0 {
-                        self.pc += 2;
-                    }
+                if input.check_key(key) == Some(false) {
+                    self.pc += 2;
                 }
             }
             (0xf, _, 0, 7) => {
@@ -284,15 +472,11 @@ impl Hardware {
             }
             (0xf, _, 1, 0xe) => self.i = self.i.wrapping_add(self.registers[x] as u16),
             (0xf, _, 0, 0xa) => {
-                if let Ok(input) = input.try_read() {
-                    // if any key is pressed
-                    let pressed_keys = input.pressed();
-                    if pressed_keys != 0 {
-                        self.registers[x] = pressed_keys.leading_zeros() as u8;
-                    } else {
-                        self.pc -= 2;
-                    }
-                } else {
+                let released = match input.try_sample() {
+                    Some(keys) => self.wait_for_key(x, keys),
+                    None => false,
+                };
+                if !released {
                     self.pc -= 2;
                 }
             }
@@ -301,35 +485,195 @@ impl Hardware {
                 // each char is 5 bytes
                 self.i = 5 * char as u16;
             }
+            (0xf, _, 3, 0) if super_chip => {
+                // each digit is 10 bytes, higher digits than 9 point behind the font
+                let digit = self.registers[x] & 0xf;
+                self.i = (BIG_FONT_START + 10 * digit as usize) as u16;
+            }
             (0xf, _, 3, 3) => {
                 let number = self.registers[x];
-                self.memory[self.i as usize] = number / 100;
-                self.memory[self.i as usize + 1] = (number % 100) / 10;
-                self.memory[self.i as usize + 2] = number % 10;
+                let digits = self.memory_mut(self.i, 3)?;
+                digits[0] = number / 100;
+                digits[1] = (number % 100) / 10;
+                digits[2] = number % 10;
             }
             (0xf, _, 5, 5) => {
-                for i in 0..=x {
-                    self.memory[self.i as usize + i] = self.registers[i];
-                }
-                if matches!(self.generation, Generation::Cosmac) {
+                let registers = self.registers;
+                self.memory_mut(self.i, x + 1)?
+                    .copy_from_slice(&registers[..=x]);
+                if self.quirks.load_store_increments_i {
                     self.i = self.i.wrapping_add(x as u16 + 1)
                 }
             }
             (0xf, _, 6, 5) => {
-                for i in 0..=x {
-                    self.registers[i] = self.memory[self.i as usize + i];
-                }
-                if matches!(self.generation, Generation::Cosmac) {
+                let memory = self.memory(self.i, x + 1)?;
+                let mut registers = self.registers;
+                registers[..=x].copy_from_slice(memory);
+                self.registers = registers;
+                if self.quirks.load_store_increments_i {
                     self.i = self.i.wrapping_add(x as u16 + 1)
                 }
             }
 
-            _ => {
-                eprintln!("unknown op code: {instr:x}");
-            }
+            _ => return Err(DecodeError::UnknownOpcode(instr)),
         }
+        Ok(commands)
+    }
+    /// Formats registers, timers and the stack for crash logs.
+    pub fn state_report(&self) -> String {
+        let mut report = format!(
+            "pc: {:04x}\ni: {:04x}\ndelay: {}\nsound: {}\n",
+            self.pc, self.i, self.delay_timer, self.sound_timer
+        );
+        for (i, reg) in self.registers.iter().enumerate() {
+            report += &format!("v{i:x}: {reg:02x}\n");
+        }
+        report += &format!("stack: {:04x?}\n", self.stack());
+        report
     }
 
+    /// Returns true if the last decoded instruction was a draw waiting for the display to sync.
+    /// The instruction didn't execute and will be retried on the next cycle.
+    pub fn stalled(&self) -> bool {
+        self.stalled
+    }
+    /// 00FE and 00FF, switches the display to `resolution`, which clears it.
+    fn set_resolution(&mut self, resolution: Resolution) -> DisplayCommand {
+        self.framebuffer.set_resolution(resolution);
+        DisplayCommand::SetResolution(resolution)
+    }
+    /// 00CN, 00FB and 00FC, moves the display contents.
+    fn scroll(&mut self, scroll: Scroll) -> DisplayCommand {
+        self.framebuffer.scroll(scroll);
+        DisplayCommand::Scroll(scroll)
+    }
+    /// 2NNN, pushes the return address and jumps to `addr`.
+    fn call_subroutine(&mut self, addr: u16) -> Result<(), DecodeError> {
+        let frame = self
+            .stack
+            .get_mut(self.stack_frame)
+            .ok_or(DecodeError::StackOverflow)?;
+        *frame = self.pc;
+        self.stack_frame += 1;
+        self.pc = addr;
+        Ok(())
+    }
+    /// 00EE, pops the return address of the innermost subroutine and jumps back to it.
+    fn return_from_subroutine(&mut self) -> Result<(), DecodeError> {
+        self.stack_frame = self
+            .stack_frame
+            .checked_sub(1)
+            .ok_or(DecodeError::StackUnderflow)?;
+        self.pc = self.stack[self.stack_frame];
+        Ok(())
+    }
+    /// FX0A, waits for a key to be pressed and released and stores it in VX.
+    /// The lowest key is picked if several are pressed at once.
+    ///
+    /// Returns true once the key was released, until then the instruction has to be repeated.
+    fn wait_for_key(&mut self, x: usize, pressed_keys: u16) -> bool {
+        match self.waiting_key {
+            Some(key) if pressed_keys & (1 << key) == 0 => {
+                self.registers[x] = key;
+                self.waiting_key = None;
+                true
+            }
+            Some(_) => false,
+            None => {
+                if pressed_keys != 0 {
+                    self.waiting_key = Some(pressed_keys.trailing_zeros() as u8);
+                }
+                false
+            }
+        }
+    }
+    /// 8XY6, shifts VX right by one and stores the shifted out bit in VF.
+    /// Shifts VY into VX instead with the shift quirk.
+    fn shift_right(&mut self, x: usize, y: usize) {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
+        let flag = self.registers[x] & 1 == 1;
+        self.registers[x] >>= 1;
+        self.set_flag(flag);
+    }
+    /// 8XYE, shifts VX left by one and stores the shifted out bit in VF.
+    /// Shifts VY into VX instead with the shift quirk.
+    fn shift_left(&mut self, x: usize, y: usize) {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
+        let flag = (self.registers[x] >> 7) == 1;
+        self.registers[x] <<= 1;
+        self.set_flag(flag);
+    }
+    /// Return addresses of the active subroutines, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_frame]
+    }
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            memory: self.memory.to_vec(),
+            stack: self.stack,
+            stack_frame: self.stack_frame,
+            i: self.i,
+            registers: self.registers,
+            pc: self.pc,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            generation: self.generation,
+            quirks: self.quirks,
+            framebuffer: self.framebuffer.clone(),
+            waiting_key: self.waiting_key,
+            halted: self.halted,
+        }
+    }
+    /// Continues from `state`. The memory of `state` has to be [`MEMORY_SIZE`] bytes long.
+    pub fn restore(&mut self, state: SaveState) {
+        self.memory.copy_from_slice(&state.memory);
+        self.stack = state.stack;
+        self.stack_frame = state.stack_frame;
+        self.i = state.i;
+        self.registers = state.registers;
+        self.pc = state.pc;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.generation = state.generation;
+        self.quirks = state.quirks;
+        self.framebuffer = state.framebuffer;
+        self.waiting_key = state.waiting_key;
+        self.halted = state.halted;
+        self.display_sync = true;
+        self.stalled = false;
+    }
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+    /// Lets a draw waiting for the display run, once the previous one was shown.
+    pub fn sync_display(&mut self) {
+        self.display_sync = true;
+    }
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    /// Returns true while the sound timer is running, which plays the buzzer.
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+    /// Returns true if the program exited and no more instructions should be executed.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+    /// Stops executing instructions, e.g. after an error.
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
     pub fn tick_cpu_clock(&mut self) {
         self.delay_timer = self.delay_timer.saturating_sub(1);
         self.sound_timer = self.sound_timer.saturating_sub(1);
@@ -338,3 +682,300 @@ impl Hardware {
         self.pc as usize
     }
 }
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chip8::clock::{Clock, ManualClock, TimerSchedule};
+
+    #[test]
+    fn timers_run_at_60_hz_for_any_instruction_rate() {
+        for instructions_per_second in [100, 540, 1080, 10_000] {
+            let mut clock = ManualClock::new();
+            let mut hardware = Hardware {
+                delay_timer: 60,
+                ..Default::default()
+            };
+            let mut timers = TimerSchedule::new(clock.now());
+            let start = clock.now();
+            while hardware.delay_timer > 0 {
+                clock.advance(Duration::from_secs(1) / instructions_per_second);
+                for _ in 0..timers.due_ticks(clock.now(), 1.) {
+                    hardware.tick_cpu_clock();
+                }
+            }
+            let elapsed = (clock.now() - start).as_secs_f64();
+            assert!(
+                (elapsed - 1.).abs() < 0.02,
+                "delay timer took {elapsed}s at {instructions_per_second} instructions per second"
+            );
+        }
+    }
+    #[test]
+    fn programs_larger_than_memory_are_rejected() {
+        let mut hardware = Hardware::default();
+        assert!(hardware.load_program(&[0xff; MAX_ROM_SIZE]).is_ok());
+        assert!(matches!(
+            hardware.load_program(&[0xff; MAX_ROM_SIZE + 1]),
+            Err(RomError::TooLarge(len)) if len == MAX_ROM_SIZE + 1
+        ));
+    }
+    #[test]
+    fn stack_overflow_and_underflow_are_errors() {
+        let mut hardware = Hardware::default();
+        assert_eq!(
+            hardware.return_from_subroutine(),
+            Err(DecodeError::StackUnderflow)
+        );
+        for _ in 0..32 {
+            hardware.call_subroutine(0x300).unwrap();
+        }
+        assert_eq!(
+            hardware.call_subroutine(0x300),
+            Err(DecodeError::StackOverflow)
+        );
+        hardware.return_from_subroutine().unwrap();
+        assert_eq!(hardware.pc, 0x300);
+    }
+    #[test]
+    fn wait_for_key_continues_on_release() {
+        let mut hardware = Hardware::default();
+        assert!(!hardware.wait_for_key(3, 0));
+        // keys 7 and 0xb pressed, the lower one is picked
+        assert!(!hardware.wait_for_key(3, 1 << 7 | 1 << 0xb));
+        assert!(!hardware.wait_for_key(3, 1 << 7));
+        assert_eq!(hardware.registers[3], 0);
+        assert!(hardware.wait_for_key(3, 1 << 0xb));
+        assert_eq!(hardware.registers[3], 7);
+    }
+    #[test]
+    fn shift_quirk_picks_the_shifted_register() {
+        // 8016: v0 = v1 >> 1 with the quirk, v0 >>= 1 without
+        for (shift_uses_vy, expected, flag) in [(true, 0b0010, 1), (false, 0b0100, 0)] {
+            let mut hardware = Hardware::default();
+            hardware.quirks.shift_uses_vy = shift_uses_vy;
+            hardware.registers[0] = 0b1000;
+            hardware.registers[1] = 0b0101;
+            hardware.shift_right(0, 1);
+            assert_eq!(hardware.registers[0], expected);
+            assert_eq!(hardware.registers[15], flag);
+        }
+    }
+    #[test]
+    fn instructions_return_their_display_changes() {
+        let mut hardware = Hardware::default();
+        hardware.set_generation(Generation::Super);
+        assert_eq!(
+            hardware.decode(0x00e0, &NO_KEYS).unwrap(),
+            [DisplayCommand::Clear]
+        );
+        assert_eq!(
+            hardware.decode(0x00ff, &NO_KEYS).unwrap(),
+            [DisplayCommand::SetResolution(Resolution::High)]
+        );
+        // the font sprite of 0 at v0, v1
+        hardware.registers[1] = 3;
+        assert_eq!(
+            hardware.decode(0xd011, &NO_KEYS).unwrap(),
+            [DisplayCommand::Draw {
+                sprite: vec![0xf000],
+                x: 0,
+                y: 3,
+                wrap: !hardware.quirks.clip_sprites,
+            }]
+        );
+        assert!(hardware.decode(0x6005, &NO_KEYS).unwrap().is_empty());
+    }
+    #[test]
+    fn is_pressed_checks_the_bit_of_the_key() {
+        assert!(is_pressed(1 << 5, 5));
+        assert!(!is_pressed(1 << 5, 0));
+        assert!(is_pressed(1 << 5, 0x15));
+    }
+    #[test]
+    fn keys_are_read_through_the_input() {
+        let mut hardware = Hardware::default();
+        hardware.registers[0] = 0xa;
+        // skip if v0 is pressed
+        hardware.decode(0xe09e, &(1u16 << 0xa)).unwrap();
+        assert_eq!(hardware.pc, PROGRAM_START as u16 + 2);
+        hardware.decode(0xe09e, &NO_KEYS).unwrap();
+        assert_eq!(hardware.pc, PROGRAM_START as u16 + 2);
+    }
+    #[test]
+    fn scrolling_moves_the_pixels() {
+        let mut hardware = Hardware::default();
+        hardware.framebuffer.draw_row(10, 5, 0x8000, false);
+        // down 3, right 4, left 8
+        for instr in [0x00c3, 0x00fb, 0x00fc, 0x00fc] {
+            hardware.decode(instr, &NO_KEYS).unwrap();
+        }
+        assert!(hardware.framebuffer.is_lit(6, 8));
+        assert!(!hardware.framebuffer.is_lit(10, 5));
+        // scrolled past the left edge
+        hardware.decode(0x00fc, &NO_KEYS).unwrap();
+        hardware.decode(0x00fc, &NO_KEYS).unwrap();
+        assert_eq!(
+            hardware.framebuffer.checksum(),
+            Framebuffer::default().checksum()
+        );
+        hardware.set_generation(Generation::Cosmac);
+        assert_eq!(
+            hardware.decode(0x00fb, &NO_KEYS),
+            Err(DecodeError::UnknownOpcode(0x00fb))
+        );
+    }
+    #[test]
+    fn load_store_increment_follows_the_quirk() {
+        for increments in [true, false] {
+            let mut hardware = Hardware {
+                i: 0x300,
+                ..Default::default()
+            };
+            hardware.quirks.load_store_increments_i = increments;
+            hardware.registers[..2].copy_from_slice(&[0xab, 0xcd]);
+            hardware.decode(0xf155, &NO_KEYS).unwrap();
+            assert_eq!(hardware.memory[0x300..0x302], [0xab, 0xcd]);
+            assert_eq!(hardware.i, if increments { 0x302 } else { 0x300 });
+            hardware.i = 0x300;
+            hardware.decode(0xf165, &NO_KEYS).unwrap();
+            assert_eq!(hardware.i, if increments { 0x302 } else { 0x300 });
+        }
+    }
+    #[test]
+    fn jump_with_offset_follows_the_quirk() {
+        for uses_vx in [true, false] {
+            let mut hardware = Hardware::default();
+            hardware.quirks.jump_uses_vx = uses_vx;
+            hardware.registers[0] = 0x10;
+            hardware.registers[3] = 0x20;
+            hardware.decode(0xb345, &NO_KEYS).unwrap();
+            assert_eq!(hardware.pc, if uses_vx { 0x365 } else { 0x355 });
+        }
+    }
+    #[test]
+    fn same_seeds_give_the_same_random_numbers() {
+        let random_numbers = |seed| {
+            let mut hardware = Hardware::default();
+            hardware.seed_rng(seed);
+            (0..32)
+                .map(|_| {
+                    hardware.decode(0xc0ff, &NO_KEYS).unwrap();
+                    hardware.registers[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+        assert_eq!(random_numbers(7), random_numbers(7));
+        assert_ne!(random_numbers(7), random_numbers(8));
+    }
+    #[test]
+    fn draws_wait_for_the_display_only_with_the_quirk() {
+        for display_wait in [true, false] {
+            let mut hardware = Hardware::default();
+            hardware.quirks.display_wait = display_wait;
+            hardware.load_program(&[0xd0, 0x01, 0xd0, 0x01]).unwrap();
+            for _ in 0..2 {
+                let instr = hardware.fetch();
+                hardware.decode(instr, &NO_KEYS).unwrap();
+            }
+            assert_eq!(hardware.stalled(), display_wait);
+            assert_eq!(hardware.pc, if display_wait { 0x202 } else { 0x204 });
+        }
+    }
+    #[test]
+    fn exit_halts_only_super_chip() {
+        let mut hardware = Hardware::default();
+        hardware.decode(0x00fd, &NO_KEYS).unwrap();
+        assert!(hardware.halted());
+        let mut hardware = Hardware::default();
+        hardware.set_generation(Generation::Cosmac);
+        assert_eq!(
+            hardware.decode(0x00fd, &NO_KEYS),
+            Err(DecodeError::UnknownOpcode(0x00fd))
+        );
+        assert!(!hardware.halted());
+    }
+    #[test]
+    fn big_font_sits_between_the_small_font_and_the_program() {
+        assert!(BIG_FONT_START >= FONT.len());
+        assert!(BIG_FONT_START + BIG_FONT.len() <= 0x200);
+        let mut hardware = Hardware::default();
+        hardware.registers[2] = 7;
+        hardware.decode(0xf230, &NO_KEYS).unwrap();
+        assert_eq!(hardware.memory(hardware.i, 10).unwrap(), &BIG_FONT[70..80]);
+    }
+    #[test]
+    fn draws_only_the_rows_of_the_sprite() {
+        let mut hardware = Hardware {
+            i: 0x300,
+            ..Default::default()
+        };
+        hardware.memory[0x300..0x303].copy_from_slice(&[0xff, 0x81, 0xff]);
+        // right below the sprite
+        hardware.framebuffer.draw_row(0, 3, 0xff00, false);
+        let rows =
+            |hardware: &Hardware| [0, 1, 2, 3].map(|y| hardware.framebuffer.sprite_row(0, y));
+        hardware.decode(0xd003, &NO_KEYS).unwrap();
+        assert_eq!(rows(&hardware), [0xff00, 0x8100, 0xff00, 0xff00]);
+        assert_eq!(hardware.registers[15], 0);
+        hardware.display_sync = true;
+        hardware.decode(0xd003, &NO_KEYS).unwrap();
+        assert_eq!(rows(&hardware), [0, 0, 0, 0xff00]);
+        assert_eq!(hardware.registers[15], 1);
+        // clipped at the bottom instead of reaching the top rows
+        hardware.registers[1] = 31;
+        hardware.display_sync = true;
+        hardware.decode(0xd013, &NO_KEYS).unwrap();
+        assert_eq!(hardware.framebuffer.sprite_row(0, 31), 0xff00);
+        assert_eq!(rows(&hardware), [0, 0, 0, 0xff00]);
+    }
+    #[test]
+    fn overlapping_sprites_set_the_flag() {
+        let mut hardware = Hardware::default();
+        // the 0 of the font, then the 1 overlapping it by a column
+        for (digit, x, flag) in [(0, 0, 0), (1, 1, 1), (1, 20, 0)] {
+            hardware.registers[0] = x;
+            hardware.i = 5 * digit;
+            hardware.display_sync = true;
+            hardware.decode(0xd015, &NO_KEYS).unwrap();
+            assert_eq!(hardware.registers[15], flag, "digit {digit} at {x}");
+        }
+    }
+    #[test]
+    fn sprites_at_the_edges_wrap_or_clip() {
+        for clip_sprites in [true, false] {
+            let mut hardware = Hardware::default();
+            hardware.quirks.clip_sprites = clip_sprites;
+            // the 0 of the font, 4 pixels wide and 5 tall, at the bottom right corner
+            hardware.registers[0] = 62;
+            hardware.registers[1] = 30;
+            hardware.decode(0xd015, &NO_KEYS).unwrap();
+            let framebuffer = hardware.framebuffer();
+            assert!(framebuffer.is_lit(62, 30) && framebuffer.is_lit(62, 31));
+            // the right half continues at the left edge, the lower rows at the top
+            assert_eq!(framebuffer.is_lit(0, 30), !clip_sprites);
+            assert_eq!(framebuffer.is_lit(62, 0), !clip_sprites);
+            assert_eq!(framebuffer.is_lit(1, 2), !clip_sprites);
+        }
+    }
+    #[test]
+    fn six_row_sprites_at_the_bottom_clip_or_wrap() {
+        for clip_sprites in [true, false] {
+            let mut hardware = Hardware::default();
+            hardware.quirks.clip_sprites = clip_sprites;
+            // a vertical line of 6 pixels at 0, 30
+            hardware.i = 0x300;
+            hardware.memory[0x300..0x306].fill(0x80);
+            hardware.registers[1] = 30;
+            hardware.decode(0xd016, &NO_KEYS).unwrap();
+            let framebuffer = hardware.framebuffer();
+            assert!(framebuffer.is_lit(0, 30) && framebuffer.is_lit(0, 31));
+            // the 4 rows past the bottom edge continue at the top
+            for y in 0..4 {
+                assert_eq!(framebuffer.is_lit(0, y), !clip_sprites);
+            }
+            assert!(!framebuffer.is_lit(0, 4));
+        }
+    }
+}
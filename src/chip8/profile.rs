@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use crate::display_bus::OpcodeCount;
+
+use super::coverage::opcode_pattern;
+
+/// Counts the executed instructions by opcode while profiling, to find the hotspots of a program.
+#[derive(Default)]
+pub struct Profile {
+    /// Count and latest instruction of each opcode pattern.
+    counts: BTreeMap<&'static str, (u64, u16)>,
+    /// Instructions executed since profiling started, including ones without a pattern.
+    total: u64,
+}
+impl Profile {
+    pub fn record(&mut self, instr: u16) {
+        self.total += 1;
+        if let Some(pattern) = opcode_pattern(instr) {
+            let (count, example) = self.counts.entry(pattern).or_default();
+            *count += 1;
+            *example = instr;
+        }
+    }
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    /// The counts of the executed opcodes, most executed first.
+    pub fn counts(&self) -> Vec<OpcodeCount> {
+        let mut counts: Vec<OpcodeCount> = self
+            .counts
+            .iter()
+            .map(|(pattern, &(count, example))| OpcodeCount {
+                pattern: pattern.to_string(),
+                count,
+                example,
+            })
+            .collect();
+        counts.sort_by_key(|count| std::cmp::Reverse(count.count));
+        counts
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_executed_opcodes_come_first() {
+        let mut profile = Profile::default();
+        for instr in [0x6001, 0x7001, 0x7102, 0x1200, 0x0000] {
+            profile.record(instr);
+        }
+        let counts = profile.counts();
+        assert_eq!(profile.total(), 5);
+        assert_eq!(counts[0].pattern, "7XNN");
+        assert_eq!(counts[0].count, 2);
+        assert_eq!(counts[0].example, 0x7102);
+        assert_eq!(counts.len(), 3);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::{self, FormatError};
+use crate::io::InputState;
+
+use super::hardware::{Generation, Quirks};
+
+/// Recorded run of a program with the keys held in every frame, played back exactly.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub program: Vec<u8>,
+    pub generation: Generation,
+    pub quirks: Quirks,
+    /// Seed of the random numbers of the run.
+    pub seed: u64,
+    /// Instructions executed per frame, the timers tick once per frame.
+    pub cycles_per_frame: u32,
+    /// Chip8 keys held in each frame.
+    pub frames: Vec<u16>,
+}
+/// A replay being recorded or played back.
+///
+/// The program only sees keys changing between frames, so a recording and its playback see the same keys.
+pub struct ReplaySession {
+    replay: Replay,
+    /// Keys the program sees during the current frame.
+    input: Arc<RwLock<InputState>>,
+    /// Instructions executed when the current frame started.
+    frame_start: u64,
+    /// File the recording is written to once it stops, `None` while playing back.
+    recording_to: Option<PathBuf>,
+    /// Frame of the replay played next.
+    next_frame: usize,
+}
+impl ReplaySession {
+    /// Starts recording `replay`, whose frames are appended from the live input.
+    pub fn record(replay: Replay, path: PathBuf, instructions: u64) -> ReplaySession {
+        ReplaySession {
+            replay,
+            input: Arc::default(),
+            frame_start: instructions,
+            recording_to: Some(path),
+            next_frame: 0,
+        }
+    }
+    pub fn play(replay: Replay, instructions: u64) -> ReplaySession {
+        ReplaySession {
+            replay,
+            input: Arc::default(),
+            frame_start: instructions,
+            recording_to: None,
+            next_frame: 0,
+        }
+    }
+    pub fn input(&self) -> &Arc<RwLock<InputState>> {
+        &self.input
+    }
+    /// Returns true once the frame that started at `frame_start` ran all its instructions.
+    pub fn frame_done(&self, instructions: u64) -> bool {
+        instructions - self.frame_start >= self.replay.cycles_per_frame as u64
+    }
+    /// Starts the next frame, recording the keys of `live` or playing back the recorded ones.
+    /// Returns false once the playback ran out of frames.
+    pub fn next_frame(&mut self, live: &Arc<RwLock<InputState>>, instructions: u64) -> bool {
+        let keys = if self.recording_to.is_some() {
+            let keys = live.write().map_or(0, |mut live| live.sample());
+            self.replay.frames.push(keys);
+            keys
+        } else {
+            let Some(&keys) = self.replay.frames.get(self.next_frame) else {
+                return false;
+            };
+            self.next_frame += 1;
+            keys
+        };
+        if let Ok(mut input) = self.input.write() {
+            input.set_keys(keys);
+        }
+        self.frame_start = instructions;
+        true
+    }
+    /// Writes the recording to its file. Playbacks have nothing to write.
+    pub fn finish(self) -> Result<(), ReplayError> {
+        match &self.recording_to {
+            Some(path) => write(path, &self.replay),
+            None => Ok(()),
+        }
+    }
+}
+#[derive(Debug)]
+pub enum ReplayError {
+    IoError(std::io::Error),
+    Incompatible(FormatError),
+    SerializeError(bincode::Error),
+}
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::IoError(e) => e.fmt(f),
+            ReplayError::Incompatible(e) => e.fmt(f),
+            ReplayError::SerializeError(e) => e.fmt(f),
+        }
+    }
+}
+impl Error for ReplayError {}
+impl From<std::io::Error> for ReplayError {
+    fn from(value: std::io::Error) -> Self {
+        ReplayError::IoError(value)
+    }
+}
+impl From<FormatError> for ReplayError {
+    fn from(value: FormatError) -> Self {
+        ReplayError::Incompatible(value)
+    }
+}
+impl From<bincode::Error> for ReplayError {
+    fn from(value: bincode::Error) -> Self {
+        ReplayError::SerializeError(value)
+    }
+}
+
+pub fn to_bytes(replay: &Replay) -> Result<Vec<u8>, ReplayError> {
+    Ok(format::with_header(&format::serialize(replay)?))
+}
+pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+    Ok(format::deserialize(format::strip_header(bytes)?)?)
+}
+pub fn write(path: &Path, replay: &Replay) -> Result<(), ReplayError> {
+    fs::write(path, to_bytes(replay)?)?;
+    Ok(())
+}
+pub fn read(path: &Path) -> Result<Replay, ReplayError> {
+    from_bytes(&fs::read(path)?)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::hardware::is_pressed;
+
+    fn replay(frames: Vec<u16>) -> Replay {
+        Replay {
+            program: vec![0xc0, 0xff, 0x12, 0x00],
+            generation: Generation::Cosmac,
+            quirks: Quirks::for_generation(Generation::Cosmac),
+            seed: 42,
+            cycles_per_frame: 11,
+            frames,
+        }
+    }
+    #[test]
+    fn replays_survive_the_file_format() {
+        let replay = replay(vec![0, 1 << 5, 1 << 5, 0]);
+        let bytes = to_bytes(&replay).unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), replay);
+        assert!(matches!(
+            from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::SerializeError(_))
+        ));
+    }
+    #[test]
+    fn playback_shows_the_recorded_keys_until_it_runs_out() {
+        let live = Arc::new(RwLock::new(InputState::default()));
+        let mut session = ReplaySession::play(replay(vec![1 << 2, 1 << 7]), 0);
+        assert!(session.next_frame(&live, 0));
+        assert!(is_pressed(session.input().read().unwrap().pressed(), 2));
+        assert!(!session.frame_done(10));
+        assert!(session.frame_done(11));
+        assert!(session.next_frame(&live, 11));
+        assert!(is_pressed(session.input().read().unwrap().pressed(), 7));
+        assert!(!is_pressed(session.input().read().unwrap().pressed(), 2));
+        assert!(!session.next_frame(&live, 22));
+    }
+}
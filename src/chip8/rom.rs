@@ -0,0 +1,153 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+use zip::{result::ZipError, ZipArchive};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+const ROM_EXTENSION: &str = "ch8";
+/// Extensions of the files [`read_rom`] can load.
+const LOADABLE_EXTENSIONS: [&str; 3] = [ROM_EXTENSION, "gz", "zip"];
+/// Address programs are loaded to.
+pub const PROGRAM_START: usize = 0x200;
+/// Size of the memory programs are loaded into, starting at [`PROGRAM_START`].
+pub const MAX_ROM_SIZE: usize = 4096 - PROGRAM_START;
+
+/// Reads the rom at `path` and checks that it fits into memory.
+///
+/// Gzip compressed roms and zip archives containing a single rom are decompressed transparently,
+/// everything else is treated as a raw rom.
+pub fn read_rom(path: &Path) -> Result<Vec<u8>, RomError> {
+    let program = decompress(fs::read(path)?)?;
+    match program.len() {
+        0 => Err(RomError::Empty),
+        len if len > MAX_ROM_SIZE => Err(RomError::TooLarge(len)),
+        _ => Ok(program),
+    }
+}
+/// Checks that `path` is a file with the extension of a rom, before reading it with [`read_rom`].
+pub fn check_rom_file(path: &Path) -> Result<(), RomError> {
+    if !path.is_file() {
+        return Err(RomError::NotAFile);
+    }
+    let loadable = path.extension().is_some_and(|ext| {
+        LOADABLE_EXTENSIONS
+            .iter()
+            .any(|loadable| ext.eq_ignore_ascii_case(loadable))
+    });
+    if !loadable {
+        return Err(RomError::UnknownExtension);
+    }
+    Ok(())
+}
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut program = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut program)?;
+        Ok(program)
+    } else if bytes.starts_with(&ZIP_MAGIC) {
+        read_zip(bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+fn read_zip(bytes: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let roms: Vec<usize> = (0..archive.len())
+        .filter(|&i| {
+            archive.by_index(i).is_ok_and(|file| {
+                file.is_file()
+                    && Path::new(file.name())
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case(ROM_EXTENSION))
+            })
+        })
+        .collect();
+    let [rom] = roms[..] else {
+        return Err(RomError::RomCountInArchive(roms.len()));
+    };
+    let mut program = Vec::new();
+    archive.by_index(rom)?.read_to_end(&mut program)?;
+    Ok(program)
+}
+#[derive(Debug)]
+pub enum RomError {
+    IoError(io::Error),
+    ZipError(ZipError),
+    /// Zip archives have to contain exactly one rom.
+    RomCountInArchive(usize),
+    Empty,
+    /// The path is a directory or doesn't exist.
+    NotAFile,
+    /// The file isn't a rom or an archive of one.
+    UnknownExtension,
+    /// The rom doesn't fit into memory.
+    TooLarge(usize),
+}
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::IoError(e) => e.fmt(f),
+            RomError::ZipError(e) => e.fmt(f),
+            RomError::RomCountInArchive(count) => write!(
+                f,
+                "The zip archive has to contain exactly one .{ROM_EXTENSION} rom but contains {count}"
+            ),
+            RomError::Empty => write!(f, "The rom is empty"),
+            RomError::NotAFile => write!(f, "The rom has to be a file"),
+            RomError::UnknownExtension => write!(
+                f,
+                "The rom has to be a .{ROM_EXTENSION} file or a .gz or .zip archive"
+            ),
+            RomError::TooLarge(len) => write!(
+                f,
+                "The rom has {len} bytes but only {MAX_ROM_SIZE} bytes fit into memory"
+            ),
+        }
+    }
+}
+impl Error for RomError {}
+impl From<io::Error> for RomError {
+    fn from(value: io::Error) -> Self {
+        RomError::IoError(value)
+    }
+}
+impl From<ZipError> for RomError {
+    fn from(value: ZipError) -> Self {
+        RomError::ZipError(value)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::chip8::hardware::{Hardware, NO_KEYS};
+
+    #[test]
+    fn empty_roms_are_rejected() {
+        let path = env::temp_dir().join(format!("chip8-empty-{}.ch8", std::process::id()));
+        fs::write(&path, []).unwrap();
+        let result = read_rom(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(RomError::Empty)));
+    }
+    #[test]
+    fn garbage_roms_fail_without_panicking() {
+        let mut rng = fastrand::Rng::with_seed(1649);
+        let garbage: Vec<u8> = (0..MAX_ROM_SIZE).map(|_| rng.u8(..)).collect();
+        let mut hardware = Hardware::default();
+        hardware.load_program(&garbage).unwrap();
+        let failed = (0..10_000).any(|_| {
+            hardware.sync_display();
+            hardware.step(&NO_KEYS).is_err()
+        });
+        assert!(failed);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{error::Error, fmt::Display, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::{self, FormatError};
+
+use super::hardware::{Generation, Quirks, MEMORY_SIZE};
+use super::screen::Framebuffer;
+
+/// Everything needed to continue a program where it was saved.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SaveState {
+    pub memory: Vec<u8>,
+    pub stack: [u16; 32],
+    pub stack_frame: usize,
+    pub i: u16,
+    pub registers: [u8; 16],
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub generation: Generation,
+    pub quirks: Quirks,
+    pub framebuffer: Framebuffer,
+    pub waiting_key: Option<u8>,
+    pub halted: bool,
+}
+#[derive(Debug)]
+pub enum SaveStateError {
+    IoError(std::io::Error),
+    Incompatible(FormatError),
+    SerializeError(bincode::Error),
+    /// The memory of the save-state doesn't have [`MEMORY_SIZE`] bytes.
+    InvalidMemorySize(usize),
+    /// A field of the save-state has a value the hardware can't have, named by the text.
+    Corrupted(&'static str),
+}
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::IoError(e) => e.fmt(f),
+            SaveStateError::Incompatible(e) => e.fmt(f),
+            SaveStateError::SerializeError(e) => e.fmt(f),
+            SaveStateError::InvalidMemorySize(size) => {
+                write!(
+                    f,
+                    "save-state has {size} bytes of memory instead of {MEMORY_SIZE}"
+                )
+            }
+            SaveStateError::Corrupted(field) => write!(f, "save-state is corrupted: {field}"),
+        }
+    }
+}
+impl Error for SaveStateError {}
+impl From<std::io::Error> for SaveStateError {
+    fn from(value: std::io::Error) -> Self {
+        SaveStateError::IoError(value)
+    }
+}
+impl From<FormatError> for SaveStateError {
+    fn from(value: FormatError) -> Self {
+        SaveStateError::Incompatible(value)
+    }
+}
+impl From<bincode::Error> for SaveStateError {
+    fn from(value: bincode::Error) -> Self {
+        SaveStateError::SerializeError(value)
+    }
+}
+
+pub fn to_bytes(state: &SaveState) -> Result<Vec<u8>, SaveStateError> {
+    Ok(format::with_header(&format::serialize(state)?))
+}
+/// Reads a save-state written by [`to_bytes`] and checks that the hardware can continue from it.
+pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, SaveStateError> {
+    let state: SaveState = format::deserialize(format::strip_header(bytes)?)?;
+    if state.memory.len() != MEMORY_SIZE {
+        return Err(SaveStateError::InvalidMemorySize(state.memory.len()));
+    }
+    if state.stack_frame > state.stack.len() {
+        return Err(SaveStateError::Corrupted(
+            "more stack frames than the stack holds",
+        ));
+    }
+    if !state.framebuffer.is_complete() {
+        return Err(SaveStateError::Corrupted("framebuffer rows are missing"));
+    }
+    if state.waiting_key.is_some_and(|key| key > 0xF) {
+        return Err(SaveStateError::Corrupted(
+            "waiting for a key that doesn't exist",
+        ));
+    }
+    Ok(state)
+}
+pub fn write(path: &Path, state: &SaveState) -> Result<(), SaveStateError> {
+    fs::write(path, to_bytes(state)?)?;
+    Ok(())
+}
+pub fn read(path: &Path) -> Result<SaveState, SaveStateError> {
+    from_bytes(&fs::read(path)?)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::hardware::Hardware;
+    use crate::chip8::screen::Resolution;
+
+    #[test]
+    fn restores_saved_hardware() {
+        let mut hardware = Hardware::default();
+        hardware.load_program(&[0x60, 0x2a, 0xa2, 0x34]).unwrap();
+        hardware.registers[3] = 0x2a;
+        hardware.i = 0x234;
+        hardware.pc = 0x204;
+        let saved = hardware.save_state();
+        let bytes = to_bytes(&saved).unwrap();
+
+        hardware.registers[3] = 0;
+        hardware.memory[0x200] = 0;
+        hardware.pc = 0x300;
+        assert_ne!(hardware.save_state(), saved);
+
+        hardware.restore(from_bytes(&bytes).unwrap());
+        assert_eq!(hardware.save_state(), saved);
+    }
+    #[test]
+    fn rejects_truncated_memory() {
+        let mut state = Hardware::default().save_state();
+        state.memory.truncate(16);
+        let bytes = to_bytes(&state).unwrap();
+        assert!(matches!(
+            from_bytes(&bytes),
+            Err(SaveStateError::InvalidMemorySize(16))
+        ));
+    }
+    #[test]
+    fn rejects_state_the_hardware_cant_have() {
+        let state = Hardware::default().save_state();
+        // bincode writes the fields of a framebuffer in order, so a shorter one can be forged
+        let rows = format::serialize(&(vec![0u128; 3], Resolution::Low)).unwrap();
+        let corrupted = [
+            SaveState {
+                stack_frame: 33,
+                ..state.clone()
+            },
+            SaveState {
+                framebuffer: format::deserialize(&rows).unwrap(),
+                ..state.clone()
+            },
+            SaveState {
+                waiting_key: Some(0x10),
+                ..state
+            },
+        ];
+        for state in corrupted {
+            let bytes = to_bytes(&state).unwrap();
+            assert!(matches!(
+                from_bytes(&bytes),
+                Err(SaveStateError::Corrupted(_))
+            ));
+        }
+    }
+}
@@ -1,36 +1,566 @@
-use crate::chip8::Pixels;
+use std::fmt::Display as FmtDisplay;
 
+use egui::Color32;
+use pixels::{wgpu, Pixels};
+use serde::{Deserialize, Serialize};
+
+/// Height of the display in the default low resolution.
 pub const SCREEN_HEIGHT: usize = 32;
+/// Width of the display in the default low resolution.
 pub const SCREEN_WIDTH: usize = 64;
+/// Height of the display in the Super-CHIP high resolution.
+pub const HIRES_HEIGHT: usize = 64;
+/// Width of the display in the Super-CHIP high resolution.
+pub const HIRES_WIDTH: usize = 128;
+
+/// Size of the display. Super-CHIP programs switch between both with 00FE and 00FF.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Resolution {
+    /// 64×32 pixels, as on the original chip8.
+    #[default]
+    Low,
+    /// 128×64 pixels.
+    High,
+}
+impl Resolution {
+    pub const fn width(self) -> usize {
+        match self {
+            Resolution::Low => SCREEN_WIDTH,
+            Resolution::High => HIRES_WIDTH,
+        }
+    }
+    pub const fn height(self) -> usize {
+        match self {
+            Resolution::Low => SCREEN_HEIGHT,
+            Resolution::High => HIRES_HEIGHT,
+        }
+    }
+}
+
+/// Colors of lit and unlit pixels.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Palette {
+    pub foreground: Color32,
+    pub background: Color32,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            foreground: Color32::LIGHT_GRAY,
+            background: Color32::BLACK,
+        }
+    }
+}
+/// Named palettes to pick from, or the user's own colors.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum PalettePreset {
+    #[default]
+    Grayscale,
+    /// Green on black, like the phosphor screens of the first chip8 computers.
+    Classic,
+    Amber,
+    /// Dark pixels on a light background.
+    Paper,
+    Custom,
+}
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 5] = [
+        PalettePreset::Grayscale,
+        PalettePreset::Classic,
+        PalettePreset::Amber,
+        PalettePreset::Paper,
+        PalettePreset::Custom,
+    ];
+    /// Colors of the preset, `None` for [`PalettePreset::Custom`].
+    pub const fn palette(self) -> Option<Palette> {
+        let (foreground, background) = match self {
+            PalettePreset::Grayscale => (Color32::LIGHT_GRAY, Color32::BLACK),
+            PalettePreset::Classic => {
+                (Color32::from_rgb(51, 255, 102), Color32::from_rgb(0, 20, 0))
+            }
+            PalettePreset::Amber => (Color32::from_rgb(255, 176, 0), Color32::from_rgb(24, 12, 0)),
+            PalettePreset::Paper => (
+                Color32::from_rgb(40, 40, 40),
+                Color32::from_rgb(232, 228, 216),
+            ),
+            PalettePreset::Custom => return None,
+        };
+        Some(Palette {
+            foreground,
+            background,
+        })
+    }
+}
+impl FmtDisplay for PalettePreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PalettePreset::Grayscale => write!(f, "grayscale"),
+            PalettePreset::Classic => write!(f, "classic green"),
+            PalettePreset::Amber => write!(f, "amber"),
+            PalettePreset::Paper => write!(f, "paper"),
+            PalettePreset::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// Super-CHIP scroll of the whole display by a number of pixels.
+/// Pixels scrolled past an edge are lost and the vacated ones are unlit.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Scroll {
+    Down(u8),
+    Left(u8),
+    Right(u8),
+}
+/// Change of the display made by an instruction, for the caller to show.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DisplayCommand {
+    Clear,
+    /// Switches the display to the resolution, which clears it.
+    SetResolution(Resolution),
+    Scroll(Scroll),
+    Draw {
+        /// 16 pixels per row, 8 pixel wide sprites only use the upper byte.
+        sprite: Vec<u16>,
+        x: u8,
+        y: u8,
+        /// Rows and columns past the screen edges wrap around instead of being clipped.
+        wrap: bool,
+    },
+}
+
+/// Logical state of the display, one bit per pixel.
+/// The leftmost pixel of a row is stored in the highest bit, so the low resolution only uses the upper half of each row.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Framebuffer {
+    /// [`HIRES_HEIGHT`] rows, of which the low resolution uses the first [`SCREEN_HEIGHT`].
+    rows: Vec<u128>,
+    resolution: Resolution,
+}
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer {
+            rows: vec![0; HIRES_HEIGHT],
+            resolution: Resolution::default(),
+        }
+    }
+}
+impl Framebuffer {
+    pub fn clear(&mut self) {
+        self.rows.fill(0);
+    }
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+    /// Switches to `resolution`, which clears the display.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.clear();
+    }
+    /// Returns false if the framebuffer doesn't have [`HIRES_HEIGHT`] rows, like one read from a corrupted file.
+    pub fn is_complete(&self) -> bool {
+        self.rows.len() == HIRES_HEIGHT
+    }
+    /// Rows of the active resolution.
+    fn rows(&self) -> &[u128] {
+        &self.rows[..self.resolution.height()]
+    }
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        x < self.resolution.width()
+            && self
+                .rows()
+                .get(y)
+                .is_some_and(|row| row & (1 << (u128::BITS as usize - 1 - x)) != 0)
+    }
+    /// Returns the 16 pixels starting at `x`, `y` as a sprite row. Pixels past the right edge are unlit.
+    pub fn sprite_row(&self, x: usize, y: usize) -> u16 {
+        let Some(row) = self.rows().get(y).filter(|_| x < self.resolution.width()) else {
+            return 0;
+        };
+        ((row << x) >> (u128::BITS - 16)) as u16
+    }
+    /// Moves all pixels of the display by `scroll`.
+    pub fn scroll(&mut self, scroll: Scroll) {
+        let width = self.resolution.width();
+        let height = self.resolution.height();
+        let visible = u128::MAX << (u128::BITS as usize - width);
+        match scroll {
+            Scroll::Down(rows) => {
+                let rows = (rows as usize).min(height);
+                self.rows.copy_within(..height - rows, rows);
+                self.rows[..rows].fill(0);
+            }
+            Scroll::Left(columns) => {
+                for row in &mut self.rows[..height] {
+                    *row = row.checked_shl(columns as u32).unwrap_or(0);
+                }
+            }
+            Scroll::Right(columns) => {
+                for row in &mut self.rows[..height] {
+                    *row = row.checked_shr(columns as u32).unwrap_or(0) & visible;
+                }
+            }
+        }
+    }
+    /// FNV-1a hash of the pixels. Stable across platforms and builds, so it can be compared to stored values.
+    pub fn checksum(&self) -> u64 {
+        let row_bytes = self.resolution.width() / 8;
+        self.rows()
+            .iter()
+            .flat_map(|row| row.to_be_bytes().into_iter().take(row_bytes))
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+    /// Draws the active resolution as text, `#` for lit and `.` for unlit pixels, one line per row.
+    /// Unlike [`Framebuffer::checksum`] it shows where two displays differ.
+    pub fn to_text(&self) -> String {
+        let width = self.resolution.width();
+        let mut text = String::with_capacity((width + 1) * self.resolution.height());
+        for y in 0..self.resolution.height() {
+            text.extend((0..width).map(|x| if self.is_lit(x, y) { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+    /// XORs the 16 pixels of `row` onto the display starting at `x`, `y`.
+    /// Pixels past the right or bottom edge wrap around if `wrap` is set and are clipped otherwise.
+    ///
+    /// Returns true if a lit pixel was turned off.
+    pub fn draw_row(&mut self, x: usize, y: usize, row: u16, wrap: bool) -> bool {
+        let resolution = self.resolution;
+        let (x, y) = wrap_position(x, y, wrap, resolution);
+        if y >= resolution.height() {
+            return false;
+        }
+        let mask = row_mask(x, row, wrap, resolution.width());
+        let collision = self.rows[y] & mask != 0;
+        self.rows[y] ^= mask;
+        collision
+    }
+    /// Draws the rows of `sprite` from `y` downwards like [`Framebuffer::draw_row`].
+    /// Rows past the bottom edge wrap around if `wrap` is set and are left out otherwise.
+    ///
+    /// Returns true if a lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u16], wrap: bool) -> bool {
+        let rows = visible_rows(y, sprite, wrap, self.resolution);
+        let mut collision = false;
+        for (n, row) in rows.iter().enumerate() {
+            collision |= self.draw_row(x, y + n, *row, wrap);
+        }
+        collision
+    }
+    /// Turns on the 16 pixels of `row` like [`Framebuffer::draw_row`] without turning any off.
+    pub fn or_row(&mut self, x: usize, y: usize, row: u16, wrap: bool) {
+        let resolution = self.resolution;
+        let (x, y) = wrap_position(x, y, wrap, resolution);
+        if y < resolution.height() {
+            self.rows[y] |= row_mask(x, row, wrap, resolution.width());
+        }
+    }
+}
+/// How sprites are combined with the display.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DrawMode {
+    /// Sprites toggle pixels, as on real hardware.
+    #[default]
+    Xor,
+    /// Sprites only turn pixels on, which shows the whole sprite for debugging.
+    Or,
+}
+/// Bits of a display row `width` pixels wide covered by the 16 pixels of `row` drawn at `x`.
+fn row_mask(x: usize, row: u16, wrap: bool, width: usize) -> u128 {
+    if x >= width {
+        return 0;
+    }
+    // move the sprite row to the leftmost pixel, then to x
+    let row = (row as u128) << (u128::BITS - 16);
+    let visible = u128::MAX << (u128::BITS as usize - width);
+    let mask = (row >> x) & visible;
+    if wrap && x + 16 > width {
+        // the pixels past the right edge continue at the left edge
+        mask | row << (width - x)
+    } else {
+        mask
+    }
+}
+
+/// Rows of `sprite` drawn at `y` that end up on the display.
+fn visible_rows(y: usize, sprite: &[u16], wrap: bool, resolution: Resolution) -> &[u16] {
+    if wrap {
+        sprite
+    } else {
+        &sprite[..sprite.len().min(resolution.height().saturating_sub(y))]
+    }
+}
 
-pub fn pixel_row(pixels: &Pixels, y: usize) -> &[u8] {
-    let frame = pixels.frame();
-    let pixel_size = 4;
-    let width = SCREEN_WIDTH * pixel_size;
-    frame.get(y * width..(y + 1) * width).unwrap_or_default()
-}
-pub fn pixel_row_mut(pixels: &mut Pixels, y: usize) -> &mut [u8] {
-    let frame = pixels.frame_mut();
-    let pixel_size = 4;
-    let width = SCREEN_WIDTH * pixel_size;
-    &mut frame[y * width..(y + 1) * width]
-}
-
-pub fn set_row(pixels: &mut Pixels, x: usize, y: usize, row: u8, color: [u8; 4]) {
-    if row == 0 {
-        return;
-    }
-    pixel_row_mut(pixels, y)
-        .chunks_exact_mut(4)
-        .skip(x)
-        .take(8)
-        .enumerate()
-        .filter(|(i, _pixel)| row & (1 << (7 - i)) != 0)
-        .for_each(|(_i, pixel)| {
-            if *pixel == [0, 0, 0, 0] {
-                pixel.copy_from_slice(&color);
+/// Wraps `x` and `y` onto the display if `wrap` is set.
+fn wrap_position(x: usize, y: usize, wrap: bool, resolution: Resolution) -> (usize, usize) {
+    if wrap {
+        (x % resolution.width(), y % resolution.height())
+    } else {
+        (x, y)
+    }
+}
+
+/// How [`Display::draw_row`] draws a row.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawOptions {
+    /// Wrap around the screen edges instead of clipping.
+    pub wrap: bool,
+    pub mode: DrawMode,
+}
+
+/// The display as shown by the app.
+///
+/// Mirrors the logical display of the emulator and optionally keeps pixels lit for a few frames
+/// after they were turned off, which steadies games that flicker from erasing and redrawing sprites.
+pub struct Display {
+    framebuffer: Framebuffer,
+    /// Frames a pixel stays lit after it was turned off. 0 turns pixels off immediately.
+    persistence: u8,
+    /// Frames left until a turned off pixel goes dark, one per pixel of the active resolution.
+    fading: Vec<u8>,
+    /// Fade turned off pixels toward the background like a phosphor screen, instead of keeping them lit.
+    phosphor: bool,
+    /// Set whenever a pixel changed.
+    changed: bool,
+    palette: Palette,
+}
+impl Default for Display {
+    fn default() -> Self {
+        Display {
+            framebuffer: Framebuffer::default(),
+            persistence: 0,
+            fading: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            phosphor: false,
+            changed: false,
+            palette: Palette::default(),
+        }
+    }
+}
+impl Display {
+    pub fn set_persistence(&mut self, pixels: &mut Pixels, frames: u8) {
+        if frames == self.persistence {
+            return;
+        }
+        self.persistence = frames;
+        if frames == 0 {
+            self.darken_faded(pixels, |_| 0);
+        }
+    }
+    /// Switches between fading turned off pixels gradually and keeping them lit until they go dark.
+    /// Takes effect with the next [`Display::decay`].
+    pub fn set_phosphor(&mut self, phosphor: bool) {
+        self.phosphor = phosphor;
+    }
+    pub fn resolution(&self) -> Resolution {
+        self.framebuffer.resolution()
+    }
+    /// Repaints the display in `palette`.
+    pub fn set_palette(&mut self, pixels: &mut Pixels, palette: Palette) {
+        if palette == self.palette {
+            return;
+        }
+        self.palette = palette;
+        let [r, g, b, _] = palette.background.to_array().map(|c| c as f64 / 255.);
+        pixels.clear_color(wgpu::Color { r, g, b, a: 1. });
+        self.repaint(pixels);
+    }
+    /// Paints every pixel from the framebuffer and the fading pixels.
+    fn repaint(&mut self, pixels: &mut Pixels) {
+        self.changed = true;
+        let width = self.resolution().width();
+        let foreground = self.palette.foreground.to_array();
+        let background = self.palette.background.to_array();
+        let frame = pixels.frame_mut().chunks_exact_mut(4);
+        for (index, (pixel, fading)) in frame.zip(&self.fading).enumerate() {
+            if self.framebuffer.is_lit(index % width, index / width) {
+                pixel.copy_from_slice(&foreground);
+            } else if *fading > 0 {
+                pixel.copy_from_slice(&self.fading_color(*fading));
+            } else {
+                pixel.copy_from_slice(&background);
+            }
+        }
+    }
+    /// Switches to `resolution` and resizes the pixel buffer to match, which clears the display.
+    pub fn set_resolution(&mut self, pixels: &mut Pixels, resolution: Resolution) {
+        let (width, height) = (resolution.width(), resolution.height());
+        if resolution != self.resolution() {
+            if let Err(e) = pixels.resize_buffer(width as u32, height as u32) {
+                eprintln!("couldn't resize the display with {e}");
+                return;
+            }
+        }
+        self.framebuffer.set_resolution(resolution);
+        self.fading = vec![0; width * height];
+        self.repaint(pixels);
+    }
+    /// Returns whether a pixel changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+    pub fn clear(&mut self, pixels: &mut Pixels) {
+        self.changed = true;
+        if self.persistence == 0 {
+            let background = self.palette.background.to_array();
+            for pixel in pixels.frame_mut().chunks_exact_mut(4) {
+                pixel.copy_from_slice(&background);
+            }
+            self.fading.fill(0);
+        } else {
+            let resolution = self.resolution();
+            for y in 0..resolution.height() {
+                for x in 0..resolution.width() {
+                    if self.framebuffer.is_lit(x, y) {
+                        self.fading[y * resolution.width() + x] = self.persistence;
+                    }
+                }
+            }
+        }
+        self.framebuffer.clear();
+    }
+    /// Scrolls the display like [`Framebuffer::scroll`] and repaints it.
+    /// Fading pixels go dark right away, since they don't move with the display.
+    pub fn scroll(&mut self, pixels: &mut Pixels, scroll: Scroll) {
+        self.framebuffer.scroll(scroll);
+        self.fading.fill(0);
+        self.repaint(pixels);
+    }
+    /// Draws the rows of `sprite` like [`Framebuffer::draw_sprite`] and paints them.
+    pub fn draw_sprite(
+        &mut self,
+        pixels: &mut Pixels,
+        x: usize,
+        y: usize,
+        sprite: &[u16],
+        options: DrawOptions,
+    ) {
+        let rows = visible_rows(y, sprite, options.wrap, self.resolution());
+        for (n, row) in rows.iter().enumerate() {
+            self.draw_row(pixels, x, y + n, *row, options);
+        }
+    }
+    /// Draws the 16 pixels of `row` onto the display like [`Framebuffer::draw_row`] and paints them.
+    pub fn draw_row(
+        &mut self,
+        pixels: &mut Pixels,
+        x: usize,
+        y: usize,
+        row: u16,
+        options: DrawOptions,
+    ) {
+        let resolution = self.resolution();
+        let width = resolution.width();
+        let (x, y) = wrap_position(x, y, options.wrap, resolution);
+        if row == 0 || x >= width || y >= resolution.height() {
+            return;
+        }
+        let DrawOptions { wrap, mode } = options;
+        match mode {
+            DrawMode::Xor => {
+                self.framebuffer.draw_row(x, y, row, wrap);
+            }
+            DrawMode::Or => self.framebuffer.or_row(x, y, row, wrap),
+        }
+        self.changed = true;
+        let frame = pixels.frame_mut();
+        for i in 0..16 {
+            let x = match x + i {
+                x if x < width => x,
+                x if wrap => x - width,
+                _ => break,
+            };
+            if row & (1 << (15 - i)) == 0 {
+                continue;
+            }
+            let index = y * width + x;
+            let pixel = &mut frame[index * 4..index * 4 + 4];
+            if self.framebuffer.is_lit(x, y) {
+                pixel.copy_from_slice(&self.palette.foreground.to_array());
+                self.fading[index] = 0;
+            } else if self.persistence == 0 {
+                pixel.copy_from_slice(&self.palette.background.to_array());
             } else {
-                pixel.fill(0);
+                self.fading[index] = self.persistence;
             }
-        });
+        }
+    }
+    /// Color of a turned off pixel with `frames` left until it goes dark.
+    fn fading_color(&self, frames: u8) -> [u8; 4] {
+        let Palette {
+            foreground,
+            background,
+        } = self.palette;
+        if !self.phosphor || self.persistence == 0 {
+            return foreground.to_array();
+        }
+        let weight = frames as f32 / self.persistence as f32;
+        let blend =
+            |lit: u8, unlit: u8| (unlit as f32 + (lit as f32 - unlit as f32) * weight) as u8;
+        let [r, g, b, a] = foreground.to_array();
+        let [br, bg, bb, ba] = background.to_array();
+        [blend(r, br), blend(g, bg), blend(b, bb), blend(a, ba)]
+    }
+    /// Counts down the frames of turned off pixels and darkens the ones that ran out.
+    /// Should be called once per rendered frame.
+    pub fn decay(&mut self, pixels: &mut Pixels) {
+        if self.persistence == 0 {
+            return;
+        }
+        self.darken_faded(pixels, |frames| frames - 1);
+    }
+    /// Updates the frames of all fading pixels with `next` and darkens those that reach 0.
+    /// With the phosphor effect, the others are dimmed by the frames they have left.
+    fn darken_faded(&mut self, pixels: &mut Pixels, next: impl Fn(u8) -> u8) {
+        let frame = pixels.frame_mut();
+        let width = self.resolution().width();
+        for index in 0..self.fading.len() {
+            let frames = self.fading[index];
+            if frames == 0 {
+                continue;
+            }
+            let frames = next(frames);
+            self.fading[index] = frames;
+            if self.framebuffer.is_lit(index % width, index / width) {
+                continue;
+            }
+            let pixel = &mut frame[index * 4..index * 4 + 4];
+            if frames == 0 {
+                pixel.copy_from_slice(&self.palette.background.to_array());
+            } else if self.phosphor {
+                pixel.copy_from_slice(&self.fading_color(frames));
+            } else {
+                continue;
+            }
+            self.changed = true;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_wrap_and_clip_at_the_active_width() {
+        let mut framebuffer = Framebuffer::default();
+        framebuffer.draw_row(60, 0, 0xff00, true);
+        assert!(framebuffer.is_lit(63, 0) && framebuffer.is_lit(3, 0));
+        assert!(!framebuffer.is_lit(64, 0) && !framebuffer.is_lit(4, 0));
+        framebuffer.set_resolution(Resolution::High);
+        assert!(!framebuffer.is_lit(63, 0));
+        framebuffer.draw_row(120, 63, 0xffff, false);
+        assert!(framebuffer.is_lit(127, 63) && !framebuffer.is_lit(0, 63));
+        assert_eq!(framebuffer.sprite_row(112, 63), 0x00ff);
+    }
+    #[test]
+    fn text_shows_the_lit_pixels_of_each_row() {
+        let mut framebuffer = Framebuffer::default();
+        framebuffer.draw_row(1, 1, 0xa000, false);
+        let text = framebuffer.to_text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), SCREEN_HEIGHT);
+        assert_eq!(lines[0], ".".repeat(SCREEN_WIDTH));
+        assert_eq!(&lines[1][..5], ".#.#.");
+    }
 }
@@ -0,0 +1,78 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use super::debug_map::map_op;
+
+/// Number of instructions kept by [`Trace`].
+pub const TRACE_LEN: usize = 256;
+
+/// Rolling buffer of the most recently executed instructions.
+/// Pushing is a single array write, so it's cheap enough to run for every instruction.
+pub struct Trace {
+    entries: [TraceEntry; TRACE_LEN],
+    next: usize,
+    len: usize,
+}
+#[derive(Default, Debug, Clone, Copy)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub op: u16,
+}
+impl Default for Trace {
+    fn default() -> Self {
+        Trace {
+            entries: [TraceEntry::default(); TRACE_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+impl Trace {
+    pub fn push(&mut self, pc: u16, op: u16) {
+        self.entries[self.next] = TraceEntry { pc, op };
+        self.next = (self.next + 1) % TRACE_LEN;
+        self.len = (self.len + 1).min(TRACE_LEN);
+    }
+    /// Iterates over the entries from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &TraceEntry> {
+        let start = (self.next + TRACE_LEN - self.len) % TRACE_LEN;
+        (0..self.len).map(move |i| &self.entries[(start + i) % TRACE_LEN])
+    }
+}
+/// Log file of every executed instruction, one per line.
+/// Writes are buffered and flushed when the log is dropped.
+pub struct TraceLog {
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+impl TraceLog {
+    /// Opens the log at `path`, appending to an existing one.
+    pub fn open(path: PathBuf) -> io::Result<TraceLog> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(TraceLog {
+            writer: BufWriter::new(file),
+            path,
+        })
+    }
+    /// Logs the instruction `op` at `pc` with the registers before it executes.
+    pub fn write(&mut self, pc: u16, op: u16, i: u16, registers: &[u8; 16]) -> io::Result<()> {
+        write!(self.writer, "pc={pc:04x} op={op:04x} i={i:04x}")?;
+        for (index, value) in registers.iter().enumerate() {
+            write!(self.writer, " v{index:x}={value:02x}")?;
+        }
+        writeln!(self.writer, " {}", map_op(op))
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+impl Drop for TraceLog {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            eprintln!("couldn't write trace log {:?} with {e}", self.path);
+        }
+    }
+}
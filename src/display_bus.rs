@@ -1,36 +1,87 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use serde::{Deserialize, Serialize};
+use winit::event_loop::EventLoopProxy;
 
 use crate::{
     app::EmulatorKind,
-    chip8::{hardware::Generation, EmulatorEvents},
+    chip8::{
+        hardware::{Generation, Quirks},
+        screen::{DisplayCommand, Resolution, Scroll},
+        EmulatorEvents,
+    },
 };
 
-#[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum AppEvents {
     #[default]
     Nop,
     EmulatorEvent(EmulatorEvents),
     ClearScreen,
+    /// Switches the display to the resolution, which clears it.
+    SetResolution(Resolution),
+    Scroll(Scroll),
     DrawSprite {
-        sprite: [u8; 16],
+        /// 16 pixels per row, 8 pixel wide sprites only use the upper byte.
+        sprite: Vec<u16>,
         x: u8,
         y: u8,
+        /// Rows and columns past the screen edges wrap around instead of being clipped.
+        wrap: bool,
     },
     SpawnEmulator {
         kind: EmulatorKind,
         generation: Generation,
+        quirks: Quirks,
         debugger: bool,
+        start_paused: bool,
         path: Option<PathBuf>,
-        fps: u32,
+        cpu_hz: u32,
+        /// Seed of the random numbers, `None` for a random seed.
+        seed: Option<u64>,
     },
     DebugEmulatorState(DebugState),
+    /// The emulator stepped back by the number of debugger steps, 0 if only the current state changed.
+    Rewound(usize),
     ClientMessage(ClientMessage),
+    ConnectionStatus(ConnectionStatus),
+    /// Stops a host and disconnects its clients.
+    CancelHosting,
+    FlagChanged(bool),
+    ProgramExited,
+    /// The played back replay ran out of frames and the emulator paused.
+    ReplayFinished,
+    /// A run to the address gave up before reaching it.
+    RunToMissed(u16),
+    /// Executed opcodes while profiling, most executed first, and the instructions executed in total.
+    OpcodeProfile {
+        counts: Vec<OpcodeCount>,
+        total: u64,
+    },
+    /// The emulator stopped because of the error.
+    EmulatorError(String),
+    /// Result of a connection test, with the error on failure.
+    ConnectionTested(Result<(), String>),
+    EmulatorStats(EmulatorStats),
 }
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
     KeyInput(u16),
+    /// Sent by a connection test instead of joining as a client.
+    ConnectionTest,
+}
+/// State of the connection between a client and its host.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ConnectionStatus {
+    /// The host accepts clients and has `clients` of them connected.
+    Hosting {
+        clients: usize,
+    },
+    Connected,
+    Reconnecting {
+        attempt: u32,
+    },
+    Disconnected,
 }
 #[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct DebugState {
@@ -38,4 +89,62 @@ pub struct DebugState {
     pub i: u16,
     pub reg: [u8; 16],
     pub op: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Address of the first byte of `memory`.
+    pub memory_start: u16,
+    /// Snapshot of the memory around pc and i. Empty unless the memory is reported.
+    pub memory: Vec<u8>,
+    /// Return addresses of the active subroutines, innermost last.
+    pub stack: Vec<u16>,
+}
+/// Counters of the running emulator, sent periodically.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct EmulatorStats {
+    /// Instructions executed since the emulator started.
+    pub instructions: u64,
+    /// Time since the emulator started.
+    pub uptime: Duration,
+    /// Instructions executed per second since the previous report.
+    pub instructions_per_second: u64,
+    /// Instructions per second the emulator is set to.
+    pub target_hz: u32,
+}
+/// How often instructions of an opcode pattern were executed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OpcodeCount {
+    /// Pattern of the opcode, like `7XNN`.
+    pub pattern: String,
+    pub count: u64,
+    /// Latest executed instruction of the pattern.
+    pub example: u16,
+}
+/// Sends `event` to the app.
+///
+/// A closed event loop is expected during shutdown, so failures are only logged.
+pub fn send_event(bus: &EventLoopProxy<AppEvents>, event: AppEvents) {
+    if let Err(e) = bus.send_event(event) {
+        eprintln!("couldn't send event to app with {e}");
+    }
+}
+/// Receiver of the events the emulator sends, so it can run without an app.
+pub trait EventSink {
+    fn send(&self, event: AppEvents);
+}
+impl EventSink for EventLoopProxy<AppEvents> {
+    fn send(&self, event: AppEvents) {
+        send_event(self, event);
+    }
+}
+impl From<DisplayCommand> for AppEvents {
+    fn from(value: DisplayCommand) -> Self {
+        match value {
+            DisplayCommand::Clear => AppEvents::ClearScreen,
+            DisplayCommand::SetResolution(resolution) => AppEvents::SetResolution(resolution),
+            DisplayCommand::Scroll(scroll) => AppEvents::Scroll(scroll),
+            DisplayCommand::Draw { sprite, x, y, wrap } => {
+                AppEvents::DrawSprite { sprite, x, y, wrap }
+            }
+        }
+    }
 }
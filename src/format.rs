@@ -0,0 +1,114 @@
+use std::{error::Error, fmt::Display};
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Marks data written by this emulator.
+pub const MAGIC: [u8; 4] = *b"CHP8";
+/// Version of all serialized structures. Has to be bumped whenever one of them changes.
+pub const FORMAT_VERSION: u16 = 40;
+pub const HEADER_LEN: usize = MAGIC.len() + 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FormatError {
+    /// The data doesn't start with [`MAGIC`].
+    UnknownFormat,
+    /// The data was written with another [`FORMAT_VERSION`].
+    IncompatibleVersion(u16),
+}
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::UnknownFormat => write!(f, "data wasn't written by a chip8 emulator"),
+            FormatError::IncompatibleVersion(version) => write!(
+                f,
+                "data has format version {version} but this build uses version {FORMAT_VERSION}"
+            ),
+        }
+    }
+}
+impl Error for FormatError {}
+
+/// The bincode configuration of all serialized data.
+/// Pinned to little endian, fixed size integers so the bytes are the same on every platform.
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_fixint_encoding()
+}
+pub fn serialize<T: Serialize>(value: &T) -> bincode::Result<Vec<u8>> {
+    bincode_options().serialize(value)
+}
+pub fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    bincode_options().deserialize(bytes)
+}
+pub fn header() -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()..].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+    header
+}
+pub fn check_header(header: &[u8; HEADER_LEN]) -> Result<(), FormatError> {
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(FormatError::UnknownFormat);
+    }
+    let version = u16::from_be_bytes([header[MAGIC.len()], header[MAGIC.len() + 1]]);
+    if version != FORMAT_VERSION {
+        return Err(FormatError::IncompatibleVersion(version));
+    }
+    Ok(())
+}
+/// Prepends the header to `payload`, used for files like save-states.
+pub fn with_header(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = header().to_vec();
+    bytes.extend_from_slice(payload);
+    bytes
+}
+/// Checks and removes the header written by [`with_header`].
+pub fn strip_header(bytes: &[u8]) -> Result<&[u8], FormatError> {
+    let Some((header, payload)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+        return Err(FormatError::UnknownFormat);
+    };
+    check_header(header)?;
+    Ok(payload)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display_bus::DebugState;
+
+    #[test]
+    fn debug_state_bytes_are_pinned() {
+        let state = DebugState {
+            pc: 0x0201,
+            i: 0x0403,
+            reg: [0xaa; 16],
+            op: 0x0605,
+            delay_timer: 0x0b,
+            sound_timer: 0x0c,
+            memory_start: 0x0e0d,
+            memory: vec![0x07, 0x08],
+            stack: vec![0x0a09],
+        };
+        let bytes = serialize(&state).unwrap();
+        let mut expected = vec![0x01, 0x02, 0x03, 0x04];
+        expected.extend([0xaa; 16]);
+        expected.extend([0x05, 0x06, 0x0b, 0x0c, 0x0d, 0x0e]);
+        expected.extend(2u64.to_le_bytes());
+        expected.extend([0x07, 0x08]);
+        expected.extend(1u64.to_le_bytes());
+        expected.extend([0x09, 0x0a]);
+        assert_eq!(bytes, expected);
+        assert_eq!(deserialize::<DebugState>(&bytes).unwrap(), state);
+    }
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = with_header(&[1, 2, 3]);
+        assert_eq!(strip_header(&bytes), Ok(&[1, 2, 3][..]));
+        bytes[MAGIC.len() + 1] ^= 1;
+        assert!(matches!(
+            strip_header(&bytes),
+            Err(FormatError::IncompatibleVersion(_))
+        ));
+    }
+}
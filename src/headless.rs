@@ -0,0 +1,230 @@
+use std::{error::Error, fmt::Display, path::Path};
+
+use crate::chip8::clock::{DEFAULT_CPU_HZ, TIMER_HZ};
+use crate::chip8::coverage::Coverage;
+use crate::chip8::hardware::{Chip8Error, Generation, Hardware, Quirks, StepOutcome, NO_KEYS};
+use crate::chip8::rom::{self, RomError};
+
+/// How a rom is run without a window.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessConfig {
+    pub generation: Generation,
+    pub quirks: Quirks,
+    /// Seed of the random numbers of CXNN, `None` for a random seed.
+    pub seed: Option<u64>,
+    /// Instructions per emulated second, which sets how often the timers count down.
+    pub cpu_hz: u32,
+    /// Instructions executed at most, unless the program exits before.
+    pub cycles: u64,
+}
+#[derive(Debug)]
+pub enum HeadlessError {
+    RomError(RomError),
+    Chip8Error(Chip8Error),
+}
+impl Display for HeadlessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadlessError::RomError(e) => e.fmt(f),
+            HeadlessError::Chip8Error(e) => e.fmt(f),
+        }
+    }
+}
+impl Error for HeadlessError {}
+impl From<RomError> for HeadlessError {
+    fn from(value: RomError) -> Self {
+        HeadlessError::RomError(value)
+    }
+}
+impl From<Chip8Error> for HeadlessError {
+    fn from(value: Chip8Error) -> Self {
+        HeadlessError::Chip8Error(value)
+    }
+}
+
+/// Runs the rom at `path` without a window and prints the final state.
+/// With `coverage` it also prints how often each opcode was executed.
+///
+/// The state is printed even if an instruction failed, to see where the program went wrong.
+pub fn run(path: &Path, config: HeadlessConfig, coverage: bool) -> Result<(), HeadlessError> {
+    let program = rom::read_rom(path)?;
+    let mut hardware = load(&program, config)?;
+    let mut coverage = coverage.then(Coverage::default);
+    let result = execute(&mut hardware, config, coverage.as_mut());
+    let executed = match result {
+        Ok(executed) | Err((_, executed)) => executed,
+    };
+    println!("cycles: {executed}");
+    println!("exited: {}", hardware.halted());
+    print!("{}", hardware.state_report());
+    println!("framebuffer: {:016x}", hardware.framebuffer().checksum());
+    if let Some(coverage) = coverage {
+        print!("{}", coverage.report());
+    }
+    result.map(|_| ()).map_err(|(e, _)| e)
+}
+/// Registers of the hardware after a run.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+}
+/// Runs `rom` as Super-CHIP for up to `cycles` instructions and returns the registers and a hash of the display.
+///
+/// The same arguments always give the same fingerprint, on every platform. The hash is the 64 bit FNV-1a hash of
+/// the rows of the display from the top, each row as the big-endian bytes of its pixels from the left, one bit per pixel.
+/// Only the active resolution is hashed, see [`Framebuffer::checksum`](crate::chip8::screen::Framebuffer::checksum).
+pub fn run_and_hash(
+    rom: &[u8],
+    quirks: Quirks,
+    seed: u64,
+    cycles: u64,
+) -> Result<(Registers, u64), HeadlessError> {
+    let config = HeadlessConfig {
+        generation: Generation::Super,
+        quirks,
+        seed: Some(seed),
+        cpu_hz: DEFAULT_CPU_HZ,
+        cycles,
+    };
+    let hardware = run_program(rom, config)?;
+    let registers = Registers {
+        v: *hardware.registers(),
+        i: hardware.i,
+        pc: hardware.pc,
+    };
+    Ok((registers, hardware.framebuffer().checksum()))
+}
+/// Runs `program` like [`run`] and returns the hardware, so tests can check the final state.
+pub fn run_program(program: &[u8], config: HeadlessConfig) -> Result<Hardware, HeadlessError> {
+    let mut hardware = load(program, config)?;
+    execute(&mut hardware, config, None).map_err(|(e, _)| e)?;
+    Ok(hardware)
+}
+/// Returns hardware of the configured generation, quirks and seed with `program` loaded.
+fn load(program: &[u8], config: HeadlessConfig) -> Result<Hardware, HeadlessError> {
+    let mut hardware = Hardware::default();
+    hardware.set_generation(config.generation);
+    hardware.set_quirks(config.quirks);
+    if let Some(seed) = config.seed {
+        hardware.seed_rng(seed);
+    }
+    hardware.load_program(program)?;
+    Ok(hardware)
+}
+/// Executes up to `config.cycles` instructions, ticking the timers at [`TIMER_HZ`] of emulated time.
+///
+/// Records the executed instructions in `coverage` if given.
+///
+/// Returns the number of executed instructions, which is also returned with the error of a failed one.
+fn execute(
+    hardware: &mut Hardware,
+    config: HeadlessConfig,
+    mut coverage: Option<&mut Coverage>,
+) -> Result<u64, (HeadlessError, u64)> {
+    // counts up by TIMER_HZ per instruction, the timers tick each time it passes cpu_hz
+    let mut timer_progress = 0;
+    for executed in 0..config.cycles {
+        if hardware.halted() {
+            return Ok(executed);
+        }
+        let instr = hardware.peek_instr();
+        // the display changes are already in the framebuffer, there's no app to show them
+        match hardware.step(&NO_KEYS) {
+            // halted only right after the exit, since halted programs don't get here
+            Ok(StepOutcome::Executed | StepOutcome::Display(_) | StepOutcome::Halted) => {
+                if let Some(coverage) = coverage.as_deref_mut() {
+                    coverage.record(instr);
+                }
+            }
+            Ok(StepOutcome::Stalled) => {}
+            Err(e) => return Err((e.into(), executed)),
+        }
+        // draws are shown instantly since there's no display to wait for
+        hardware.sync_display();
+        timer_progress += TIMER_HZ;
+        while timer_progress >= config.cpu_hz {
+            timer_progress -= config.cpu_hz;
+            hardware.tick_cpu_clock();
+        }
+    }
+    Ok(config.cycles)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(cycles: u64) -> HeadlessConfig {
+        HeadlessConfig {
+            generation: Generation::Super,
+            quirks: Quirks::for_generation(Generation::Super),
+            seed: None,
+            cpu_hz: 60,
+            cycles,
+        }
+    }
+    #[test]
+    fn runs_until_the_program_exits() {
+        let mut hardware = Hardware::default();
+        // draw the font sprite of 0 at 0, 0 and exit
+        hardware
+            .load_program(&[0x00, 0xe0, 0xa0, 0x00, 0xd0, 0x05, 0x00, 0xfd, 0x12, 0x08])
+            .unwrap();
+        assert_eq!(execute(&mut hardware, config(100), None).ok(), Some(4));
+        assert!(hardware.halted());
+        assert!(hardware.framebuffer().is_lit(0, 0));
+        assert_eq!(hardware.framebuffer().sprite_row(0, 1), 0x9000);
+    }
+    #[test]
+    fn coverage_counts_the_executed_opcodes() {
+        let mut hardware = Hardware::default();
+        // v0 = 5, add 1 to v0 twice and exit
+        hardware
+            .load_program(&[0x60, 0x05, 0x70, 0x01, 0x70, 0x01, 0x00, 0xfd])
+            .unwrap();
+        let mut coverage = Coverage::default();
+        execute(&mut hardware, config(100), Some(&mut coverage)).unwrap();
+        assert_eq!(
+            coverage.report(),
+            "opcode  count\n00FD    1\n6XNN    1\n7XNN    2\n"
+        );
+    }
+    #[test]
+    fn stops_after_the_cycles() {
+        let mut hardware = Hardware::default();
+        // set the delay timer to 5 and loop forever
+        hardware
+            .load_program(&[0x60, 0x05, 0xf0, 0x15, 0x12, 0x04])
+            .unwrap();
+        assert_eq!(execute(&mut hardware, config(4), None).ok(), Some(4));
+        assert!(!hardware.halted());
+        // one tick per instruction at 60 Hz, starting with the one setting the timer
+        assert_eq!(hardware.delay_timer(), 2);
+    }
+    #[test]
+    fn same_seeds_give_the_same_fingerprint() {
+        // draw the font sprite of 0 at a random position and loop forever
+        let rom = [0xc0, 0xff, 0xc1, 0xff, 0xa0, 0x00, 0xd0, 0x15, 0x12, 0x08];
+        let quirks = Quirks::for_generation(Generation::Super);
+        let fingerprint = run_and_hash(&rom, quirks, 7, 10).unwrap();
+        assert_eq!(run_and_hash(&rom, quirks, 7, 10).unwrap(), fingerprint);
+        assert_eq!(fingerprint.0.pc, 0x208);
+    }
+    #[test]
+    fn jumps_with_offset_add_the_register_of_the_quirk() {
+        // v0 = 0x10, v3 = 0x20, jump to 0x300 plus the offset
+        let rom = [0x60, 0x10, 0x63, 0x20, 0xb3, 0x00];
+        for (jump_uses_vx, target) in [(false, 0x310), (true, 0x320)] {
+            let config = HeadlessConfig {
+                quirks: Quirks {
+                    jump_uses_vx,
+                    ..Quirks::for_generation(Generation::Super)
+                },
+                ..config(3)
+            };
+            let hardware = run_program(&rom, config).unwrap();
+            assert_eq!(hardware.pc, target);
+        }
+    }
+}
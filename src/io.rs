@@ -1,15 +1,29 @@
 // use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, RwLock};
 
 use serde::{Deserialize, Serialize};
 use winit::event::VirtualKeyCode;
 use winit_input_helper::WinitInputHelper;
 
+use self::keymap::KeyMap;
+use crate::chip8::hardware::{self, Chip8Input};
+pub mod gamepad;
+pub mod keymap;
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Copy, Default)]
 pub struct InputState {
     pub quit: bool,
     keys: u16,
-    client: u16,
+    /// Keys held by each client of a host, indexed by the slot of the client.
+    clients: [u16; MAX_CLIENTS],
+    /// Client keys pressed since the emulator last sampled the input.
+    /// Keeps quick taps of a client from being overwritten before the emulator sees them.
+    client_pending: u16,
+    /// Keys held on gamepads.
+    gamepad: u16,
 }
+/// Number of clients a host accepts at once.
+pub const MAX_CLIENTS: usize = 8;
 pub const KEY_MAP: [VirtualKeyCode; 16] = [
     VirtualKeyCode::X,
     VirtualKeyCode::Key1,
@@ -30,10 +44,34 @@ pub const KEY_MAP: [VirtualKeyCode; 16] = [
 ];
 impl InputState {
     pub const fn pressed(self) -> u16 {
-        self.keys | self.client
+        self.keys | self.client_keys() | self.client_pending | self.gamepad
+    }
+    /// Keys held by any client.
+    const fn client_keys(&self) -> u16 {
+        let mut keys = 0;
+        let mut slot = 0;
+        while slot < MAX_CLIENTS {
+            keys |= self.clients[slot];
+            slot += 1;
+        }
+        keys
+    }
+    /// Returns the pressed keys for the emulator and forgets client keys that were released since the last sample.
+    pub fn sample(&mut self) -> u16 {
+        let pressed = self.pressed();
+        self.client_pending = self.client_keys();
+        pressed
     }
-    pub fn update(&mut self, input: &WinitInputHelper) {
-        for (i, key) in KEY_MAP.into_iter().enumerate() {
+    /// Returns whether the chip8 `key` is pressed and forgets a quick tap of it by a client.
+    /// Taps of other keys are kept until their key is checked.
+    pub fn check_key(&mut self, key: u8) -> bool {
+        let pressed = hardware::is_pressed(self.pressed(), key);
+        let bit = 1 << (key & 0xF);
+        self.client_pending = (self.client_pending & !bit) | (self.client_keys() & bit);
+        pressed
+    }
+    pub fn update(&mut self, input: &WinitInputHelper, key_map: &KeyMap) {
+        for (i, key) in key_map.iter().copied().enumerate() {
             if input.key_pressed(key) {
                 self.keys |= 1 << i;
             }
@@ -42,7 +80,64 @@ impl InputState {
             }
         }
     }
-    pub fn set_client_keys(&mut self, other: u16) {
-        self.client = other;
+    /// Sets the keys held by the client in `slot`. Slots past [`MAX_CLIENTS`] are ignored.
+    pub fn set_client_keys(&mut self, slot: usize, keys: u16) {
+        if let Some(client) = self.clients.get_mut(slot) {
+            *client = keys;
+            self.client_pending |= keys;
+        }
+    }
+    /// Sets the keys held on the keyboard, like the recorded keys of a replay.
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys;
+    }
+    pub fn set_gamepad_keys(&mut self, keys: u16) {
+        self.gamepad = keys;
+    }
+}
+/// Samples the shared input, so released client keys are seen once.
+impl Chip8Input for Arc<RwLock<InputState>> {
+    fn sample(&self) -> Option<u16> {
+        self.write().ok().map(|mut input| input.sample())
+    }
+    fn check_key(&self, key: u8) -> Option<bool> {
+        self.write().ok().map(|mut input| input.check_key(key))
+    }
+    fn try_sample(&self) -> Option<u16> {
+        self.try_write().ok().map(|mut input| input.sample())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::hardware::Hardware;
+
+    #[test]
+    fn client_keys_combine() {
+        let mut input = InputState::default();
+        input.set_client_keys(0, 1 << 2);
+        input.set_client_keys(3, 1 << 8);
+        assert_eq!(input.sample(), 1 << 2 | 1 << 8);
+        input.set_client_keys(3, 0);
+        // the released key is still seen once, like a quick tap
+        assert_eq!(input.sample(), 1 << 2 | 1 << 8);
+        assert_eq!(input.sample(), 1 << 2);
+    }
+    #[test]
+    fn client_taps_last_until_their_key_is_checked() {
+        let input = Arc::new(RwLock::new(InputState::default()));
+        let mut hardware = Hardware::default();
+        hardware.registers[0] = 7;
+        hardware.registers[1] = 4;
+        // key 4 is tapped between two samples
+        input.write().unwrap().set_client_keys(0, 1 << 4);
+        input.write().unwrap().set_client_keys(0, 0);
+        // checking key 7 first doesn't use up the tap
+        hardware.decode(0xe09e, &input).unwrap();
+        assert_eq!(hardware.pc, 0x200);
+        hardware.decode(0xe19e, &input).unwrap();
+        assert_eq!(hardware.pc, 0x202);
+        hardware.decode(0xe19e, &input).unwrap();
+        assert_eq!(hardware.pc, 0x202);
     }
 }
@@ -0,0 +1,175 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use gilrs::{Button, EventType, GamepadId, Gilrs};
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+use super::keymap::KeyConfigError;
+
+/// Gamepad button for each of the 16 chip8 keys, indexed by the chip8 key.
+/// Chip8 keys without a button can only be pressed on the keyboard.
+pub type ButtonMap = [Option<Button>; 16];
+
+/// Default buttons, putting the directions most games use on the d-pad and 5 on the main face button.
+pub const BUTTON_MAP: ButtonMap = {
+    let mut buttons = [None; 16];
+    buttons[0x2] = Some(Button::DPadUp);
+    buttons[0x4] = Some(Button::DPadLeft);
+    buttons[0x6] = Some(Button::DPadRight);
+    buttons[0x8] = Some(Button::DPadDown);
+    buttons[0x5] = Some(Button::South);
+    buttons[0x0] = Some(Button::East);
+    buttons[0xa] = Some(Button::West);
+    buttons[0xb] = Some(Button::North);
+    buttons[0x1] = Some(Button::Start);
+    buttons[0xf] = Some(Button::Select);
+    buttons
+};
+
+/// Layout of a button config file, mapping hex digits of chip8 keys to gilrs button names.
+///
+/// ```toml
+/// [buttons]
+/// 2 = "DPadUp"
+/// 5 = "South"
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+struct ButtonConfig {
+    buttons: BTreeMap<String, Button>,
+}
+
+/// Parses a button config and checks that no button is mapped to more than one chip8 key.
+pub fn parse(text: &str) -> Result<ButtonMap, KeyConfigError> {
+    let config: ButtonConfig = toml::from_str(text)?;
+    let mut button_map = [None; 16];
+    for (name, button) in config.buttons {
+        let index = match u8::from_str_radix(&name, 16) {
+            Ok(index) if name.len() == 1 => index as usize,
+            _ => return Err(KeyConfigError::UnknownKey(name)),
+        };
+        if button_map.contains(&Some(button)) {
+            return Err(KeyConfigError::DuplicateButton(button));
+        }
+        button_map[index] = Some(button);
+    }
+    Ok(button_map)
+}
+/// Maps the chip8 `key` to `button`.
+/// The chip8 key that was mapped to `button` before gets the old button of `key`.
+pub fn rebind(button_map: &mut ButtonMap, key: usize, button: Button) {
+    if let Some(other) = button_map.iter().position(|&b| b == Some(button)) {
+        button_map[other] = button_map[key];
+    }
+    button_map[key] = Some(button);
+}
+pub fn to_toml(button_map: &ButtonMap) -> Result<String, KeyConfigError> {
+    let buttons = button_map
+        .iter()
+        .enumerate()
+        .filter_map(|(i, button)| Some((format!("{i:X}"), (*button)?)))
+        .collect();
+    Ok(toml::to_string(&ButtonConfig { buttons })?)
+}
+pub fn load(path: &Path) -> Result<ButtonMap, KeyConfigError> {
+    parse(&fs::read_to_string(path)?)
+}
+pub fn save(path: &Path, button_map: &ButtonMap) -> Result<(), KeyConfigError> {
+    fs::write(path, to_toml(button_map)?)?;
+    Ok(())
+}
+/// Loads the button config from the config directory.
+///
+/// Writes the default layout there if there's no config yet, and falls back to it if the config is invalid.
+pub fn load_or_create() -> ButtonMap {
+    let Some(path) = paths::button_config() else {
+        return BUTTON_MAP;
+    };
+    if !path.exists() {
+        if let Err(e) = save(&path, &BUTTON_MAP) {
+            eprintln!("couldn't write button config {path:?} with {e}");
+        }
+        return BUTTON_MAP;
+    }
+    load(&path).unwrap_or_else(|e| {
+        eprintln!("couldn't load button config {path:?} with {e}");
+        BUTTON_MAP
+    })
+}
+
+/// All connected gamepads and the buttons held on each of them.
+pub struct Gamepads {
+    gilrs: Gilrs,
+    held: HashMap<GamepadId, HashSet<Button>>,
+}
+impl Gamepads {
+    /// Starts listening for gamepads. Returns `None` if gamepads aren't supported on this system.
+    pub fn new() -> Option<Gamepads> {
+        let gilrs = Gilrs::new()
+            .inspect_err(|e| eprintln!("couldn't initialize gamepads with {e}"))
+            .ok()?;
+        Some(Gamepads {
+            gilrs,
+            held: HashMap::new(),
+        })
+    }
+    /// Handles all pending gamepad events and returns the buttons that were pressed since the last poll.
+    ///
+    /// Buttons of a disconnected gamepad count as released.
+    pub fn poll(&mut self) -> Vec<Button> {
+        let mut pressed = Vec::new();
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    self.held.entry(event.id).or_default().insert(button);
+                    pressed.push(button);
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(held) = self.held.get_mut(&event.id) {
+                        held.remove(&button);
+                    }
+                }
+                EventType::Disconnected => {
+                    self.held.remove(&event.id);
+                }
+                _ => {}
+            }
+        }
+        pressed
+    }
+    /// Returns the chip8 keys held on any gamepad, one bit per key.
+    pub fn keys(&self, button_map: &ButtonMap) -> u16 {
+        keys(self.held.values().flatten(), button_map)
+    }
+}
+/// Chip8 keys of the `held` buttons, one bit per key.
+fn keys<'a>(held: impl IntoIterator<Item = &'a Button>, button_map: &ButtonMap) -> u16 {
+    held.into_iter()
+        .filter_map(|button| button_map.iter().position(|&b| b == Some(*button)))
+        .fold(0, |keys, key| keys | 1 << key)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_a_used_button_swaps() {
+        let mut button_map = BUTTON_MAP;
+        rebind(&mut button_map, 0x2, Button::South);
+        assert_eq!(button_map[0x2], Some(Button::South));
+        assert_eq!(button_map[0x5], Some(Button::DPadUp));
+        rebind(&mut button_map, 0x3, Button::RightTrigger);
+        assert_eq!(button_map[0x3], Some(Button::RightTrigger));
+        assert_eq!(parse(&to_toml(&button_map).unwrap()).unwrap(), button_map);
+    }
+    #[test]
+    fn held_buttons_set_their_keys() {
+        let held = [Button::DPadUp, Button::South, Button::RightThumb];
+        assert_eq!(keys(&held, &BUTTON_MAP), 1 << 0x2 | 1 << 0x5);
+        assert_eq!(keys(&[], &BUTTON_MAP), 0);
+    }
+}
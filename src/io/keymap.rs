@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, error::Error, fmt::Display, fs, path::Path};
+
+use gilrs::Button;
+use serde::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
+
+use crate::paths;
+
+use super::KEY_MAP;
+
+/// Keyboard key for each of the 16 chip8 keys, indexed by the chip8 key.
+pub type KeyMap = [VirtualKeyCode; 16];
+
+/// Layout of a key config file, mapping each hex digit of a chip8 key to a winit key name.
+///
+/// ```toml
+/// [keys]
+/// 0 = "X"
+/// 1 = "Key1"
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyConfig {
+    keys: BTreeMap<String, VirtualKeyCode>,
+}
+#[derive(Debug)]
+pub enum KeyConfigError {
+    IoError(std::io::Error),
+    ParseError(toml::de::Error),
+    SerializeError(toml::ser::Error),
+    /// The chip8 key isn't mapped to a keyboard key.
+    MissingKey(u8),
+    /// The name isn't a hex digit of a chip8 key.
+    UnknownKey(String),
+    /// The keyboard key is mapped to more than one chip8 key.
+    DuplicateKey(VirtualKeyCode),
+    /// The gamepad button is mapped to more than one chip8 key.
+    DuplicateButton(Button),
+}
+impl Display for KeyConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyConfigError::IoError(e) => e.fmt(f),
+            KeyConfigError::ParseError(e) => e.fmt(f),
+            KeyConfigError::SerializeError(e) => e.fmt(f),
+            KeyConfigError::MissingKey(key) => write!(f, "chip8 key {key:X} isn't mapped"),
+            KeyConfigError::UnknownKey(name) => {
+                write!(
+                    f,
+                    "{name} isn't a chip8 key, expected a hex digit from 0 to F"
+                )
+            }
+            KeyConfigError::DuplicateKey(key) => {
+                write!(f, "{key:?} is mapped to more than one chip8 key")
+            }
+            KeyConfigError::DuplicateButton(button) => {
+                write!(f, "{button:?} is mapped to more than one chip8 key")
+            }
+        }
+    }
+}
+impl Error for KeyConfigError {}
+impl From<std::io::Error> for KeyConfigError {
+    fn from(value: std::io::Error) -> Self {
+        KeyConfigError::IoError(value)
+    }
+}
+impl From<toml::de::Error> for KeyConfigError {
+    fn from(value: toml::de::Error) -> Self {
+        KeyConfigError::ParseError(value)
+    }
+}
+impl From<toml::ser::Error> for KeyConfigError {
+    fn from(value: toml::ser::Error) -> Self {
+        KeyConfigError::SerializeError(value)
+    }
+}
+
+/// Parses a key config and checks that every chip8 key is mapped to its own keyboard key.
+pub fn parse(text: &str) -> Result<KeyMap, KeyConfigError> {
+    let config: KeyConfig = toml::from_str(text)?;
+    let mut key_map: [Option<VirtualKeyCode>; 16] = [None; 16];
+    for (name, key) in config.keys {
+        let index = match u8::from_str_radix(&name, 16) {
+            Ok(index) if name.len() == 1 => index as usize,
+            _ => return Err(KeyConfigError::UnknownKey(name)),
+        };
+        if key_map.contains(&Some(key)) {
+            return Err(KeyConfigError::DuplicateKey(key));
+        }
+        key_map[index] = Some(key);
+    }
+    let mut keys = KEY_MAP;
+    for (i, key) in key_map.into_iter().enumerate() {
+        keys[i] = key.ok_or(KeyConfigError::MissingKey(i as u8))?;
+    }
+    Ok(keys)
+}
+/// Maps the chip8 `key` to `keyboard_key`.
+/// The chip8 key that was mapped to `keyboard_key` before gets the old keyboard key of `key`.
+pub fn rebind(key_map: &mut KeyMap, key: usize, keyboard_key: VirtualKeyCode) {
+    if let Some(other) = key_map.iter().position(|&k| k == keyboard_key) {
+        key_map[other] = key_map[key];
+    }
+    key_map[key] = keyboard_key;
+}
+pub fn to_toml(key_map: &KeyMap) -> Result<String, KeyConfigError> {
+    let keys = key_map
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (format!("{i:X}"), *key))
+        .collect();
+    Ok(toml::to_string(&KeyConfig { keys })?)
+}
+pub fn load(path: &Path) -> Result<KeyMap, KeyConfigError> {
+    parse(&fs::read_to_string(path)?)
+}
+pub fn save(path: &Path, key_map: &KeyMap) -> Result<(), KeyConfigError> {
+    fs::write(path, to_toml(key_map)?)?;
+    Ok(())
+}
+/// Loads the key config from the config directory.
+///
+/// Writes the default layout there if there's no config yet, and falls back to it if the config is invalid.
+pub fn load_or_create() -> KeyMap {
+    let Some(path) = paths::key_config() else {
+        return KEY_MAP;
+    };
+    if !path.exists() {
+        if let Err(e) = save(&path, &KEY_MAP) {
+            eprintln!("couldn't write key config {path:?} with {e}");
+        }
+        return KEY_MAP;
+    }
+    load(&path).unwrap_or_else(|e| {
+        eprintln!("couldn't load key config {path:?} with {e}");
+        KEY_MAP
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_a_used_key_swaps() {
+        let mut key_map = KEY_MAP;
+        rebind(&mut key_map, 0x0, KEY_MAP[0x5]);
+        assert_eq!(key_map[0x0], KEY_MAP[0x5]);
+        assert_eq!(key_map[0x5], KEY_MAP[0x0]);
+        rebind(&mut key_map, 0x1, VirtualKeyCode::M);
+        assert_eq!(key_map[0x1], VirtualKeyCode::M);
+        assert_eq!(parse(&to_toml(&key_map).unwrap()).unwrap(), key_map);
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use chip8::hardware::Generation;
+use clap::Parser;
+
+pub mod app;
+pub mod chip8;
+pub mod display_bus;
+pub mod format;
+pub mod headless;
+pub mod io;
+pub mod paths;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "A chip8 emulator")]
+pub struct Args {
+    /// Rom to start the emulator with.
+    #[arg(long)]
+    pub rom: Option<PathBuf>,
+    /// Chip8 interpreter to emulate, which also sets its quirks.
+    #[arg(long, value_enum)]
+    pub generation: Option<Generation>,
+    /// Instructions executed per second, independent of the 60 Hz of the timers.
+    #[arg(long, alias = "fps", value_parser = clap::value_parser!(u32).range(1..))]
+    pub cpu_hz: Option<u32>,
+    /// Runs the rom without a window and prints the final registers and a hash of the display.
+    #[arg(long, requires = "rom")]
+    pub headless: bool,
+    /// Seed of the random numbers in headless mode, so runs can be reproduced.
+    #[arg(long, requires = "headless")]
+    pub seed: Option<u64>,
+    /// Instructions executed at most in headless mode.
+    #[arg(long, default_value_t = 10_000)]
+    pub cycles: u64,
+    /// Prints how often each opcode was executed in headless mode.
+    #[arg(long, requires = "headless")]
+    pub coverage: bool,
+}
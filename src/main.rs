@@ -1,11 +1,30 @@
-use app::App;
+use std::process;
+
+use chip8::app::App;
+use chip8::chip8::clock::DEFAULT_CPU_HZ;
+use chip8::chip8::hardware::Quirks;
+use chip8::headless::{self, HeadlessConfig};
+use chip8::Args;
+use clap::Parser;
 use pixels::Error;
-mod app;
-mod chip8;
-mod display_bus;
-mod io;
 
 fn main() -> Result<(), Error> {
-    let app = App::init()?;
+    let args = Args::parse();
+    if let (true, Some(rom)) = (args.headless, &args.rom) {
+        let generation = args.generation.unwrap_or_default();
+        let config = HeadlessConfig {
+            generation,
+            quirks: Quirks::for_generation(generation),
+            seed: args.seed,
+            cpu_hz: args.cpu_hz.unwrap_or(DEFAULT_CPU_HZ),
+            cycles: args.cycles,
+        };
+        if let Err(e) = headless::run(rom, config, args.coverage) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let app = App::init(&args)?;
     app.run()
 }
@@ -0,0 +1,62 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "chip8")
+}
+/// Creates `dir` if it doesn't exist yet and returns it.
+fn ensure_dir(dir: PathBuf) -> Option<PathBuf> {
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("couldn't create directory {dir:?} with {e}");
+        return None;
+    }
+    Some(dir)
+}
+/// Root directory for everything the emulator writes to disk.
+pub fn data_dir() -> Option<PathBuf> {
+    ensure_dir(project_dirs()?.data_dir().to_path_buf())
+}
+/// Directory holding configuration files such as settings and keybindings.
+pub fn config_dir() -> Option<PathBuf> {
+    ensure_dir(project_dirs()?.config_dir().to_path_buf())
+}
+/// Directory for save-states.
+pub fn save_dir() -> Option<PathBuf> {
+    ensure_dir(data_dir()?.join("saves"))
+}
+/// Directory for screenshots of the emulator display.
+pub fn screenshot_dir() -> Option<PathBuf> {
+    ensure_dir(data_dir()?.join("screenshots"))
+}
+/// Directory for memory dumps.
+pub fn dump_dir() -> Option<PathBuf> {
+    ensure_dir(data_dir()?.join("dumps"))
+}
+/// File the emulator writes its recent instructions and state to when it crashes.
+pub fn crash_log() -> Option<PathBuf> {
+    Some(data_dir()?.join("crash.log"))
+}
+/// File mapping the chip8 keys to keyboard keys.
+pub fn key_config() -> Option<PathBuf> {
+    Some(config_dir()?.join("keys.toml"))
+}
+/// File holding the settings of the gui.
+pub fn settings() -> Option<PathBuf> {
+    Some(config_dir()?.join("settings.toml"))
+}
+/// File listing the most recently opened roms.
+pub fn recent_roms() -> Option<PathBuf> {
+    Some(config_dir()?.join("recent_roms.txt"))
+}
+/// File mapping the chip8 keys to gamepad buttons.
+pub fn button_config() -> Option<PathBuf> {
+    Some(config_dir()?.join("buttons.toml"))
+}
+/// Opens `dir` in the file browser of the OS.
+pub fn open_dir(dir: &Path) -> io::Result<()> {
+    open::that(dir)
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::Path;
+
+use chip8::chip8::clock::DEFAULT_CPU_HZ;
+use chip8::chip8::hardware::{Generation, Quirks};
+use chip8::headless::{self, HeadlessConfig};
+
+/// Runs the rom in `assets` for `cycles` and compares the display to the image in `tests/reference`.
+fn assert_display(rom: &str, generation: Generation, cycles: u64, reference: &str) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let program = fs::read(root.join("assets").join(rom)).unwrap();
+    let config = HeadlessConfig {
+        generation,
+        quirks: Quirks::for_generation(generation),
+        seed: None,
+        cpu_hz: DEFAULT_CPU_HZ,
+        cycles,
+    };
+    let hardware = headless::run_program(&program, config).unwrap();
+    let display = hardware.framebuffer().to_text();
+    let expected = fs::read_to_string(root.join("tests/reference").join(reference)).unwrap();
+    assert!(
+        display == expected,
+        "{rom} differs from {reference}:\n{display}"
+    );
+}
+#[test]
+fn ibm_logo() {
+    assert_display("2-ibm-logo.ch8", Generation::Cosmac, 100, "ibm-logo.txt");
+}
+#[test]
+fn opcode_test() {
+    assert_display(
+        "test_opcode.ch8",
+        Generation::Super,
+        1000,
+        "test-opcode.txt",
+    );
+}
+#[test]
+fn corax_plus_opcode_test() {
+    assert_display("3-corax+.ch8", Generation::Super, 1000, "corax-plus.txt");
+}